@@ -0,0 +1,182 @@
+use pdb::{PDB, Source};
+
+use crate::{Error, Result};
+
+/// Stream number of the debug information stream.
+const DBI_STREAM: u32 = 3;
+
+/// The parts of the DBI stream header which the pdb crate doesn't expose.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct DbiHeader {
+    /// Must match the age in the CodeView record of the executable.
+    pub age: u32,
+    /// `IMAGE_FILE_MACHINE_*` constant of the target.
+    pub machine_type: u16,
+    /// Stream containing the `IMAGE_SECTION_HEADER`s of the executable.
+    pub section_header_stream: Option<u32>,
+}
+
+impl DbiHeader {
+    pub fn read<'s, S: Source<'s> + 's>(pdb: &mut PDB<'s, S>) -> Result<DbiHeader> {
+        let stream = pdb.raw_stream(DBI_STREAM)?;
+        let mut buf = stream.parse_buffer();
+        let len = buf.len();
+        DbiHeader::parse(buf.take(len)?)
+    }
+
+    fn parse(data: &[u8]) -> Result<DbiHeader> {
+        // https://llvm.org/docs/PDB/DbiStream.html#stream-header
+        if read_u32(data, 0)? != 0xffff_ffff {
+            return Err(pdb::Error::UnimplementedFeature("ancient DBI header").into());
+        }
+        // the optional debug header follows the substreams whose sizes are in the header
+        let substreams: usize = [24, 28, 32, 36, 40, 52].iter()
+            .map(|&offset| read_u32(data, offset).map(|size| size as usize))
+            .sum::<Result<_>>()?;
+        let debug_header = HEADER_SIZE + substreams;
+        let debug_header_size = read_u32(data, 48)? as usize;
+        let section_header_stream = if debug_header_size >= 2 * (SECTION_HEADER_DATA + 1) {
+            match read_u16(data, debug_header + 2 * SECTION_HEADER_DATA)? {
+                0xffff => None,
+                stream => Some(stream as u32),
+            }
+        } else {
+            None
+        };
+        Ok(DbiHeader {
+            age: read_u32(data, 8)?,
+            machine_type: read_u16(data, 58)?,
+            section_header_stream,
+        })
+    }
+}
+
+/// Size of the DBI stream header.
+const HEADER_SIZE: usize = 64;
+/// Index of the section header stream in the optional debug header.
+const SECTION_HEADER_DATA: usize = 5;
+/// Size of an `IMAGE_SECTION_HEADER`.
+const SECTION_HEADER_SIZE: usize = 40;
+
+/// The addresses of the sections of the executable, used to turn the segment and offset of
+/// symbols into RVAs.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Sections {
+    /// `VirtualAddress` of each section, segment numbers start at 1.
+    addresses: Vec<u32>,
+}
+
+impl Sections {
+    pub fn read<'s, S: Source<'s> + 's>(pdb: &mut PDB<'s, S>) -> Result<Sections> {
+        let stream = match DbiHeader::read(pdb)?.section_header_stream {
+            Some(stream) => pdb.raw_stream(stream)?,
+            None => return Ok(Sections::default()),
+        };
+        let mut buf = stream.parse_buffer();
+        let len = buf.len();
+        Sections::parse(buf.take(len)?)
+    }
+
+    fn parse(data: &[u8]) -> Result<Sections> {
+        let addresses = (0..data.len() / SECTION_HEADER_SIZE)
+            .map(|i| read_u32(data, i * SECTION_HEADER_SIZE + 12))
+            .collect::<Result<_>>()?;
+        Ok(Sections { addresses })
+    }
+
+    pub fn rva(&self, segment: u16, offset: u32) -> Option<u32> {
+        let index = (segment as usize).checked_sub(1)?;
+        self.addresses.get(index)?.checked_add(offset)
+    }
+}
+
+pub(crate) fn read_u16(data: &[u8], offset: usize) -> Result<u16> {
+    offset.checked_add(2).and_then(|end| data.get(offset..end))
+        .map(|b| u16::from_le_bytes(b.try_into().unwrap()))
+        .ok_or(Error::Pdb(pdb::Error::UnexpectedEof))
+}
+
+pub(crate) fn read_u32(data: &[u8], offset: usize) -> Result<u32> {
+    offset.checked_add(4).and_then(|end| data.get(offset..end))
+        .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+        .ok_or(Error::Pdb(pdb::Error::UnexpectedEof))
+}
+
+pub(crate) fn read_u64(data: &[u8], offset: usize) -> Result<u64> {
+    offset.checked_add(8).and_then(|end| data.get(offset..end))
+        .map(|b| u64::from_le_bytes(b.try_into().unwrap()))
+        .ok_or(Error::Pdb(pdb::Error::UnexpectedEof))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A DBI stream header followed by `modules` bytes of module info and the optional debug
+    /// header, whose section header entry is `section_headers`.
+    fn dbi(modules: usize, section_headers: u16) -> Vec<u8> {
+        let mut data = vec![0; HEADER_SIZE + modules];
+        data[0..4].copy_from_slice(&0xffff_ffffu32.to_le_bytes());
+        data[8..12].copy_from_slice(&3u32.to_le_bytes());
+        data[24..28].copy_from_slice(&(modules as u32).to_le_bytes());
+        let debug_header: Vec<u8> = (0..11u16)
+            .flat_map(|i| if i as usize == SECTION_HEADER_DATA { section_headers } else { 0xffff }.to_le_bytes())
+            .collect();
+        data[48..52].copy_from_slice(&(debug_header.len() as u32).to_le_bytes());
+        data[58..60].copy_from_slice(&0x8664u16.to_le_bytes());
+        data.extend(debug_header);
+        data
+    }
+
+    #[test]
+    fn header() {
+        let header = DbiHeader::parse(&dbi(12, 7)).unwrap();
+        assert_eq!(header.age, 3);
+        assert_eq!(header.machine_type, 0x8664);
+        assert_eq!(header.section_header_stream, Some(7));
+        assert_eq!(DbiHeader::parse(&dbi(0, 0xffff)).unwrap().section_header_stream, None);
+    }
+
+    #[test]
+    fn header_without_debug_header() {
+        let mut data = dbi(0, 7);
+        data[48..52].copy_from_slice(&4u32.to_le_bytes());
+        assert_eq!(DbiHeader::parse(&data).unwrap().section_header_stream, None);
+    }
+
+    #[test]
+    fn invalid_header() {
+        let mut data = dbi(0, 7);
+        data[0..4].copy_from_slice(&0u32.to_le_bytes());
+        assert!(matches!(DbiHeader::parse(&data), Err(Error::Pdb(pdb::Error::UnimplementedFeature(_)))));
+        let data = dbi(0, 7);
+        assert!(matches!(DbiHeader::parse(&data[..50]), Err(Error::Pdb(pdb::Error::UnexpectedEof))));
+        // the debug header is cut off
+        assert!(matches!(DbiHeader::parse(&data[..data.len() - 12]), Err(Error::Pdb(pdb::Error::UnexpectedEof))));
+    }
+
+    #[test]
+    fn sections() {
+        let mut data = vec![0; 2 * SECTION_HEADER_SIZE + 3];
+        data[12..16].copy_from_slice(&0x1000u32.to_le_bytes());
+        data[SECTION_HEADER_SIZE + 12..SECTION_HEADER_SIZE + 16].copy_from_slice(&0xffff_f000u32.to_le_bytes());
+        let sections = Sections::parse(&data).unwrap();
+        assert_eq!(sections.rva(1, 0x10), Some(0x1010));
+        assert_eq!(sections.rva(2, 0xfff), Some(0xffff_ffff));
+        assert_eq!(sections.rva(2, 0x1000), None);
+        assert_eq!(sections.rva(0, 0x10), None);
+        assert_eq!(sections.rva(3, 0x10), None);
+    }
+
+    #[test]
+    fn read_integers() {
+        let data = [1, 2, 3, 4, 5, 6, 7, 8];
+        assert_eq!(read_u16(&data, 6).unwrap(), 0x0807);
+        assert_eq!(read_u32(&data, 1).unwrap(), 0x0504_0302);
+        assert_eq!(read_u64(&data, 0).unwrap(), 0x0807_0605_0403_0201);
+        assert!(read_u16(&data, 7).is_err());
+        assert!(read_u32(&data, 5).is_err());
+        assert!(read_u64(&data, 1).is_err());
+        assert!(read_u32(&data, usize::MAX - 1).is_err());
+    }
+}
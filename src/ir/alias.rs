@@ -0,0 +1,58 @@
+use crate::ir::{ClassFieldKind, Converter, Name, TypeIndex};
+use crate::symbols::Udt;
+use crate::Result;
+
+/// A typedef, e.g. `typedef int int32;`.
+///
+/// The pdb only knows the names of typedefs from their `S_UDT` symbols. The type records refer
+/// to the aliased types directly, so a field refers to an alias if its type record is named by
+/// exactly one typedef, see `ClassField::alias`.
+#[derive(Debug)]
+pub struct Alias {
+    pub name: Name,
+    pub kind: ClassFieldKind,
+}
+
+impl Alias {
+    pub fn from(converter: &mut Converter, name: &str, idx: pdb::TypeIndex) -> Result<Alias> {
+        converter.set_field(Some(name.to_string()));
+        let kind = ClassFieldKind::from(converter, idx);
+        converter.set_field(None);
+        Ok(Alias {
+            name: Name::from(name.to_string()),
+            kind: kind?,
+        })
+    }
+}
+
+impl Converter<'_, '_> {
+    /// Converts the typedefs of the `S_UDT` symbols, skipping those which only name a class,
+    /// union or enum by its own name.
+    ///
+    /// In lenient mode, typedefs whose type can't be converted are recorded in the `Report`
+    /// and left out.
+    pub(crate) fn convert_aliases(&mut self, udts: &[Udt]) -> Result<Vec<Alias>> {
+        let mut aliases = Vec::new();
+        for udt in udts {
+            let alias = match Alias::from(self, &udt.name, udt.type_index) {
+                Ok(alias) => alias,
+                Err(e) if self.lenient => {
+                    self.record_failure(udt.name.clone(), udt.type_index, e);
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            let index = match alias.kind {
+                ClassFieldKind::Class(c) => Some(TypeIndex::Class(c)),
+                ClassFieldKind::Enum(e) => Some(TypeIndex::Enum(e)),
+                ClassFieldKind::Union(u) => Some(TypeIndex::Union(u)),
+                _ => None,
+            };
+            if index.is_some_and(|index| self.arena.name_of(index).name == udt.name) {
+                continue;
+            }
+            aliases.push(alias);
+        }
+        Ok(aliases)
+    }
+}
@@ -1,42 +1,110 @@
 use std::collections::HashMap;
+use std::fmt;
+use std::mem;
 use std::ops::{Index, IndexMut};
 
 use pdb;
 
-use crate::ir::{Class, Enum, Union, Name, Size};
+use crate::{Error, MachineType, Result};
+use crate::ir::{Class, ClassMember, Enum, Union, Name, Size, Globals, Functions, Alias, Constants};
 
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
 pub struct ClassIndex(pub usize);
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
 pub struct EnumIndex(pub usize);
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
 pub struct UnionIndex(pub usize);
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
 pub enum TypeIndex {
     Class(ClassIndex),
     Enum(EnumIndex),
     Union(UnionIndex),
 }
 
+/// The pdb record a type was converted from.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Origin {
+    Pdb(pdb::TypeIndex),
+    /// The type has no record of its own, but was split off the members of `parent`.
+    Synthesized { parent: pdb::TypeIndex },
+}
+
+impl Origin {
+    /// The index of the type's own record, `None` for synthesized types.
+    pub fn pdb_index(self) -> Option<pdb::TypeIndex> {
+        match self {
+            Origin::Pdb(idx) => Some(idx),
+            Origin::Synthesized { .. } => None,
+        }
+    }
+
+    /// The origin of a type split off a type of this origin.
+    pub fn synthesized(self) -> Origin {
+        match self {
+            Origin::Pdb(parent) | Origin::Synthesized { parent } => Origin::Synthesized { parent },
+        }
+    }
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Origin::Pdb(idx) => write!(f, "pdb type {:#x}", idx),
+            Origin::Synthesized { parent } => write!(f, "synthesized from pdb type {:#x}", parent),
+        }
+    }
+}
+
 pub struct Arena {
     classes: Vec<Class>,
     enums: Vec<Enum>,
     unions: Vec<Union>,
     type_names: HashMap<String, TypeIndex>,
+    /// All types of each name, in insertion order.
+    candidates: HashMap<String, Vec<TypeIndex>>,
+    /// Names which got new candidates since `type_names` was last updated.
+    unchosen: Vec<String>,
+    duplicate_policy: DuplicatePolicy,
     index_map: HashMap<pdb::TypeIndex, TypeIndex>,
+    unique_names: HashMap<pdb::TypeIndex, String>,
+    globals: Globals,
+    functions: Functions,
+    aliases: Vec<Alias>,
+    /// The first alias of each name.
+    alias_names: HashMap<String, usize>,
+    constants: Constants,
+    machine_type: MachineType,
 }
 
 impl Arena {
-    pub fn new() -> Arena {
+    pub fn new(machine_type: MachineType) -> Arena {
         Arena {
+            machine_type,
             classes: Vec::new(),
             enums: Vec::new(),
             unions: Vec::new(),
             type_names: HashMap::new(),
+            candidates: HashMap::new(),
+            unchosen: Vec::new(),
+            duplicate_policy: DuplicatePolicy::default(),
             index_map: HashMap::new(),
+            unique_names: HashMap::new(),
+            globals: Globals::default(),
+            functions: Functions::default(),
+            aliases: Vec::new(),
+            alias_names: HashMap::new(),
+            constants: Constants::default(),
         }
     }
 
+    pub fn machine_type(&self) -> MachineType {
+        self.machine_type
+    }
+
+    pub fn pointer_size(&self) -> usize {
+        self.machine_type.pointer_size()
+    }
+
     pub fn classes(&self) -> &Vec<Class> {
         &self.classes
     }
@@ -56,10 +124,18 @@ impl Arena {
         &self.enums
     }
 
+    pub fn enums_mut(&mut self) -> &mut Vec<Enum> {
+        &mut self.enums
+    }
+
     pub fn unions(&self) -> &Vec<Union> {
         &self.unions
     }
 
+    pub fn unions_mut(&mut self) -> &mut Vec<Union> {
+        &mut self.unions
+    }
+
     pub fn type_names(&self) -> &HashMap<String, TypeIndex> {
         &self.type_names
     }
@@ -69,54 +145,252 @@ impl Arena {
     }
 
     pub fn insert_class(&mut self, class: Class, idx: pdb::TypeIndex) -> ClassIndex {
-        let index = self.insert_custom_class(class);
+        let index = ClassIndex(self.classes.len());
         self.index_map.insert(idx, TypeIndex::Class(index));
-        index
+        self.insert_custom_class(class)
     }
     pub fn insert_custom_class(&mut self, class: Class) -> ClassIndex {
         let index = ClassIndex(self.classes.len());
-        self.insert_name(class.name.name.clone(), TypeIndex::Class(index), class.size, class.members.len());
+        let name = class.name.name.clone();
         self.classes.push(class);
+        self.insert_name(name, TypeIndex::Class(index));
         index
     }
     pub fn insert_enum(&mut self, e: Enum, idx: pdb::TypeIndex) -> EnumIndex {
-        let index = self.insert_custom_enum(e);
+        let index = EnumIndex(self.enums.len());
         self.index_map.insert(idx, TypeIndex::Enum(index));
-        index
+        self.insert_custom_enum(e)
     }
     pub fn insert_custom_enum(&mut self, e: Enum) -> EnumIndex {
         let index = EnumIndex(self.enums.len());
-        self.insert_name(e.name.name.clone(), TypeIndex::Enum(index), e.size(self), e.variants.len());
+        let name = e.name.name.clone();
         self.enums.push(e);
+        self.insert_name(name, TypeIndex::Enum(index));
         index
     }
     pub fn insert_union(&mut self, u: Union, idx: pdb::TypeIndex) -> UnionIndex {
-        let index = self.insert_custom_union(u);
+        let index = UnionIndex(self.unions.len());
         self.index_map.insert(idx, TypeIndex::Union(index));
-        index
+        self.insert_custom_union(u)
     }
     pub fn insert_custom_union(&mut self, u: Union) -> UnionIndex {
         let index = UnionIndex(self.unions.len());
-        self.insert_name(u.name.name.clone(), TypeIndex::Union(index), u.size, u.fields.len());
+        let name = u.name.name.clone();
         self.unions.push(u);
+        self.insert_name(name, TypeIndex::Union(index));
         index
     }
 
+    /// Replaces an already inserted class, e.g. a placeholder inserted before conversion.
+    pub fn replace_class(&mut self, index: ClassIndex, class: Class) {
+        let name = class.name.name.clone();
+        self.classes[index.0] = class;
+        self.insert_name(name, TypeIndex::Class(index));
+    }
+    pub fn replace_enum(&mut self, index: EnumIndex, e: Enum) {
+        let name = e.name.name.clone();
+        self.enums[index.0] = e;
+        self.insert_name(name, TypeIndex::Enum(index));
+    }
+    pub fn replace_union(&mut self, index: UnionIndex, u: Union) {
+        let name = u.name.name.clone();
+        self.unions[index.0] = u;
+        self.insert_name(name, TypeIndex::Union(index));
+    }
+
     // For some reason some types are inside the pdb multiple times with
     // varying size and fields.
-    // For a string-lookup, we usually only care about the largest one.
-    fn insert_name(&mut self, name: String, index: TypeIndex, size: usize, fields_len: usize) {
-        if let Some(old) = self.type_names.get(&name) {
-            let (old_size, old_fields) = match *old {
-                TypeIndex::Class(c) => (self[c].size(self), self[c].members.len()),
-                TypeIndex::Enum(e) => (self[e].size(self), self[e].variants.len()),
-                TypeIndex::Union(u) => (self[u].size(self), self[u].fields.len()),
+    // Which of them a name refers to is decided by the `DuplicatePolicy` once all types are
+    // converted, see `choose_type_names`.
+    fn insert_name(&mut self, name: String, index: TypeIndex) {
+        let candidates = self.candidates.entry(name.clone()).or_default();
+        if !candidates.contains(&index) {
+            candidates.push(index);
+            self.unchosen.push(name);
+        }
+    }
+
+    /// Decides which type each name with new types refers to.
+    ///
+    /// Until then, those names don't refer to the new types, so types still being converted
+    /// always use the types they were converted with.
+    pub fn choose_type_names(&mut self) {
+        let mut names = mem::take(&mut self.unchosen);
+        names.sort();
+        names.dedup();
+        for name in names {
+            let chosen = self.choose(&name);
+            self.type_names.insert(name, chosen);
+        }
+    }
+
+    fn choose(&self, name: &str) -> TypeIndex {
+        let candidates = &self.candidates[name];
+        let with_origin = |pred: &dyn Fn(pdb::TypeIndex) -> bool| candidates.iter().copied()
+            .find(|&index| self.origin(index).pdb_index().is_some_and(&pred));
+        let chosen = match &self.duplicate_policy {
+            DuplicatePolicy::Largest | DuplicatePolicy::Error => None,
+            DuplicatePolicy::First => candidates.first().copied(),
+            DuplicatePolicy::Last => candidates.last().copied(),
+            DuplicatePolicy::PdbIndex(chosen) => chosen.get(name)
+                .and_then(|&chosen| with_origin(&|idx| idx == chosen)),
+            DuplicatePolicy::UniqueName(chosen) => chosen.get(name)
+                .and_then(|chosen| with_origin(&|idx| self.unique_names.get(&idx) == Some(chosen))),
+        };
+        chosen.unwrap_or_else(|| self.largest(candidates))
+    }
+
+    /// The candidate with the largest size, of those the one with the most members and of
+    /// those the first one.
+    fn largest(&self, candidates: &[TypeIndex]) -> TypeIndex {
+        let mut chosen = candidates[0];
+        for &candidate in &candidates[1..] {
+            if self.size_and_members(candidate) > self.size_and_members(chosen) {
+                chosen = candidate;
+            }
+        }
+        chosen
+    }
+
+    fn size_and_members(&self, index: TypeIndex) -> (usize, usize) {
+        match index {
+            TypeIndex::Class(c) => (self[c].size(self), self[c].members.len()),
+            TypeIndex::Enum(e) => (self[e].size(self), self[e].variants.len()),
+            TypeIndex::Union(u) => (self[u].size(self), self[u].fields.len()),
+        }
+    }
+
+    pub fn duplicate_policy(&self) -> &DuplicatePolicy {
+        &self.duplicate_policy
+    }
+
+    /// Changes which of several types with the same name is used for that name.
+    ///
+    /// With `DuplicatePolicy::Error`, this fails if a name belongs to more than one type which
+    /// isn't opaque.
+    pub fn set_duplicate_policy(&mut self, policy: DuplicatePolicy) -> Result<()> {
+        self.duplicate_policy = policy;
+        self.unchosen.clear();
+        let mut names: Vec<_> = self.candidates.keys().cloned().collect();
+        names.sort();
+        for name in names {
+            if let DuplicatePolicy::Error = self.duplicate_policy {
+                let conflicting: Vec<_> = self.candidates[&name].iter()
+                    .filter(|&&index| !self.is_opaque(index))
+                    .map(|&index| self.origin(index).pdb_index())
+                    .collect();
+                if conflicting.len() > 1 {
+                    return Err(Error::DuplicateType { name, candidates: conflicting });
+                }
+            }
+            let chosen = self.choose(&name);
+            self.type_names.insert(name, chosen);
+        }
+        Ok(())
+    }
+
+    /// Sets the unique names of the pdb types, used by `DuplicatePolicy::UniqueName`.
+    pub fn set_unique_names(&mut self, unique_names: HashMap<pdb::TypeIndex, String>) {
+        self.unique_names = unique_names;
+    }
+
+    /// Sets the RVAs of the static members of all classes from the addresses of the global
+    /// variables, which are named `Class::member`.
+    pub fn set_static_member_addresses(&mut self, addresses: &HashMap<String, u32>) {
+        for class in &mut self.classes {
+            for member in &mut class.static_members {
+                member.rva = addresses.get(&format!("{}::{}", class.name.name, member.name.name)).copied();
+            }
+        }
+    }
+
+    /// The global and static variables, empty unless the whole pdb was converted.
+    pub fn globals(&self) -> &Globals {
+        &self.globals
+    }
+
+    pub fn set_globals(&mut self, globals: Globals) {
+        self.globals = globals;
+    }
+
+    /// The functions of the binary, empty unless the whole pdb was converted.
+    pub fn functions(&self) -> &Functions {
+        &self.functions
+    }
+
+    pub fn set_functions(&mut self, functions: Functions) {
+        self.functions = functions;
+    }
+
+    /// The typedefs, empty unless the whole pdb was converted.
+    pub fn aliases(&self) -> &[Alias] {
+        &self.aliases
+    }
+
+    pub fn get_alias(&self, name: &str) -> Option<&Alias> {
+        self.alias_names.get(name).map(|&index| &self.aliases[index])
+    }
+
+    pub fn set_aliases(&mut self, aliases: Vec<Alias>) {
+        self.alias_names.clear();
+        for (index, alias) in aliases.iter().enumerate() {
+            self.alias_names.entry(alias.name.name.clone()).or_insert(index);
+        }
+        self.aliases = aliases;
+    }
+
+    /// The named constants, empty unless the whole pdb was converted.
+    pub fn constants(&self) -> &Constants {
+        &self.constants
+    }
+
+    pub fn set_constants(&mut self, constants: Constants) {
+        self.constants = constants;
+    }
+
+    /// All types with the given name, in the order they were inserted.
+    pub fn candidates(&self, name: &str) -> Vec<Candidate> {
+        let chosen = self.type_names.get(name);
+        let candidates = self.candidates.get(name).map(Vec::as_slice).unwrap_or_default();
+        candidates.iter().map(|&index| {
+            let pdb_index = self.origin(index).pdb_index();
+            let members = match index {
+                TypeIndex::Class(c) => self[c].members.iter().map(|member| match member {
+                    ClassMember::Vtable => "vtable".to_string(),
+                    ClassMember::VirtualBasePointer(_) => "vbptr".to_string(),
+                    ClassMember::BaseClass(base) => format!("base {}", self[base.base_class].name.name),
+                    ClassMember::VirtualBaseClass(base) => format!("virtual base {}", self[base.base_class].name.name),
+                    ClassMember::Field(field) => field.name.name.clone(),
+                }).collect(),
+                TypeIndex::Enum(e) => self[e].variants.iter().map(|v| v.name.name.clone()).collect(),
+                TypeIndex::Union(u) => self[u].fields.iter().map(|f| f.name.name.clone()).collect(),
             };
-            if old_size >= size && old_fields >= fields_len {
-                return;
+            Candidate {
+                index,
+                pdb_index,
+                unique_name: pdb_index.and_then(|idx| self.unique_names.get(&idx)).cloned(),
+                size: self.size_and_members(index).0,
+                members,
+                opaque: self.is_opaque(index),
+                chosen: chosen == Some(&index),
             }
+        }).collect()
+    }
+
+    pub fn is_opaque(&self, index: TypeIndex) -> bool {
+        match index {
+            TypeIndex::Class(c) => self[c].opaque,
+            TypeIndex::Enum(e) => self[e].opaque,
+            TypeIndex::Union(u) => self[u].opaque,
+        }
+    }
+
+    pub fn origin(&self, index: TypeIndex) -> Origin {
+        match index {
+            TypeIndex::Class(c) => self[c].origin,
+            TypeIndex::Enum(e) => self[e].origin,
+            TypeIndex::Union(u) => self[u].origin,
         }
-        self.type_names.insert(name, index);
     }
 
     pub fn get_class(&self, index: ClassIndex) -> Option<&Class> {
@@ -137,67 +411,86 @@ impl Arena {
     pub fn get_union_mut(&mut self, index: UnionIndex) -> Option<&mut Union> {
         self.unions.get_mut(index.0)
     }
+    pub fn name_of(&self, index: TypeIndex) -> &Name {
+        match index {
+            TypeIndex::Class(c) => &self[c].name,
+            TypeIndex::Enum(e) => &self[e].name,
+            TypeIndex::Union(u) => &self[u].name,
+        }
+    }
     pub fn get_type_by_name(&self, index: &str) -> Option<&TypeIndex> {
         self.type_names.get(index)
     }
 
-    pub fn get_largest_class_index(&self, index: ClassIndex) -> ClassIndex {
-        let class = &self[index];
-        let new_index = match self[&class.name] {
-            TypeIndex::Class(c) => c,
-            _ => unreachable!()
-        };
-        let new_class = &self[new_index];
-        if class.size(self) >= new_class.size(self) && class.members.len() >= new_class.members.len() {
-            index
-        } else {
-            new_index
+    /// The class which the name of the given class refers to according to the `DuplicatePolicy`.
+    pub fn resolve_class_index(&self, index: ClassIndex) -> ClassIndex {
+        match self.type_names.get(&self[index].name.name) {
+            Some(&TypeIndex::Class(chosen)) => chosen,
+            _ => index,
         }
     }
-    pub fn get_largest_class(&self, index: ClassIndex) -> &Class {
-        self.get_class(self.get_largest_class_index(index)).unwrap()
+    pub fn resolve_class(&self, index: ClassIndex) -> &Class {
+        &self[self.resolve_class_index(index)]
     }
-    pub fn get_largest_enum_index(&self, index: EnumIndex) -> EnumIndex {
-        let e = &self[index];
-        let new_index = match self[&e.name] {
-            TypeIndex::Enum(e) => e,
-            _ => unreachable!()
-        };
-        let new_e = &self[new_index];
-        if e.size(self) >= new_e.size(self) && e.variants.len() >= new_e.variants.len() {
-            index
-        } else {
-            new_index
+    pub fn resolve_enum_index(&self, index: EnumIndex) -> EnumIndex {
+        match self.type_names.get(&self[index].name.name) {
+            Some(&TypeIndex::Enum(chosen)) => chosen,
+            _ => index,
         }
     }
-    pub fn get_largest_enum(&self, index: EnumIndex) -> &Enum {
-        self.get_enum(self.get_largest_enum_index(index)).unwrap()
+    pub fn resolve_enum(&self, index: EnumIndex) -> &Enum {
+        &self[self.resolve_enum_index(index)]
     }
-    pub fn get_largest_union_index(&self, index: UnionIndex) -> UnionIndex {
-        let u = &self[index];
-        let new_index = match self[&u.name] {
-            TypeIndex::Union(u) => u,
-            _ => unreachable!()
-        };
-        let new_u = &self[new_index];
-        if u.size(self) >= new_u.size(self) && u.fields.len() >= new_u.fields.len() {
-            index
-        } else {
-            new_index
+    pub fn resolve_union_index(&self, index: UnionIndex) -> UnionIndex {
+        match self.type_names.get(&self[index].name.name) {
+            Some(&TypeIndex::Union(chosen)) => chosen,
+            _ => index,
         }
     }
-    pub fn get_largest_union(&self, index: UnionIndex) -> &Union {
-        self.get_union(self.get_largest_union_index(index)).unwrap()
+    pub fn resolve_union(&self, index: UnionIndex) -> &Union {
+        &self[self.resolve_union_index(index)]
     }
-    pub fn get_largest_type_index(&self, index: TypeIndex) -> TypeIndex {
+    pub fn resolve_type_index(&self, index: TypeIndex) -> TypeIndex {
         match index {
-            TypeIndex::Class(c) => TypeIndex::Class(self.get_largest_class_index(c)),
-            TypeIndex::Enum(e) => TypeIndex::Enum(self.get_largest_enum_index(e)),
-            TypeIndex::Union(u) => TypeIndex::Union(self.get_largest_union_index(u))
+            TypeIndex::Class(c) => TypeIndex::Class(self.resolve_class_index(c)),
+            TypeIndex::Enum(e) => TypeIndex::Enum(self.resolve_enum_index(e)),
+            TypeIndex::Union(u) => TypeIndex::Union(self.resolve_union_index(u))
         }
     }
 }
 
+/// Decides which of several types with the same name a name refers to.
+#[derive(Debug, Clone, Default)]
+pub enum DuplicatePolicy {
+    /// The type with the largest size, of those the one with the most members.
+    #[default]
+    Largest,
+    /// The type converted first.
+    First,
+    /// The type converted last.
+    Last,
+    /// The type converted from the given pdb record, `Largest` for names not contained.
+    PdbIndex(HashMap<String, pdb::TypeIndex>),
+    /// The type with the given unique (decorated) name, `Largest` for names not contained.
+    UniqueName(HashMap<String, String>),
+    /// Fail if a name refers to multiple types.
+    Error,
+}
+
+/// One of the types with a given name, see `Arena::candidates`.
+#[derive(Debug, Clone)]
+pub struct Candidate {
+    pub index: TypeIndex,
+    /// The pdb record the type was converted from, `None` for synthesized types.
+    pub pdb_index: Option<pdb::TypeIndex>,
+    pub unique_name: Option<String>,
+    pub size: usize,
+    pub members: Vec<String>,
+    pub opaque: bool,
+    /// The name refers to this type according to the `DuplicatePolicy`.
+    pub chosen: bool,
+}
+
 impl Index<ClassIndex> for Arena {
     type Output = Class;
 
@@ -252,3 +545,100 @@ impl<'a> Index<&'a Name> for Arena {
         self.get_type_by_name(&index.name).unwrap()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Alignment;
+    use crate::ir::{ClassKind, Properties};
+
+    fn class(size: usize, members: usize, idx: pdb::TypeIndex, opaque: bool) -> Class {
+        Class {
+            name: Name::from("Foo".to_string()),
+            kind: ClassKind::Struct,
+            members: (0..members).map(|_| ClassMember::Vtable).collect(),
+            properties: Properties::default(),
+            nested_types: Vec::new(),
+            static_members: Vec::new(),
+            virtual_methods: Vec::new(),
+            vtable_shape: None,
+            derived_from: Vec::new(),
+            size,
+            alignment: Alignment::None,
+            opaque,
+            origin: Origin::Pdb(idx),
+        }
+    }
+
+    /// Four classes named `Foo`, where comparing size or members alone isn't transitive.
+    fn arena() -> Arena {
+        let mut arena = Arena::new(MachineType::Amd64);
+        arena.insert_class(class(8, 1, 0x1000, false), 0x1000);
+        arena.insert_class(class(16, 1, 0x1001, false), 0x1001);
+        arena.insert_class(class(16, 3, 0x1002, false), 0x1002);
+        arena.insert_class(class(4, 5, 0x1003, false), 0x1003);
+        arena.set_unique_names(HashMap::from([(0x1003, ".?AUFoo@a@@".to_string())]));
+        arena
+    }
+
+    fn chosen(policy: DuplicatePolicy) -> Option<pdb::TypeIndex> {
+        let mut arena = arena();
+        arena.set_duplicate_policy(policy).unwrap();
+        arena.origin(arena.type_names()["Foo"]).pdb_index()
+    }
+
+    #[test]
+    fn names_are_chosen_after_conversion() {
+        let mut arena = arena();
+        assert!(arena.type_names().is_empty());
+        arena.choose_type_names();
+        assert_eq!(arena.type_names()["Foo"], TypeIndex::Class(ClassIndex(2)));
+        assert_eq!(arena.candidates("Foo").len(), 4);
+    }
+
+    #[test]
+    fn largest() {
+        assert_eq!(chosen(DuplicatePolicy::Largest), Some(0x1002));
+    }
+
+    #[test]
+    fn first_and_last() {
+        assert_eq!(chosen(DuplicatePolicy::First), Some(0x1000));
+        assert_eq!(chosen(DuplicatePolicy::Last), Some(0x1003));
+    }
+
+    #[test]
+    fn pdb_index() {
+        let policy = |idx| DuplicatePolicy::PdbIndex(HashMap::from([("Foo".to_string(), idx)]));
+        assert_eq!(chosen(policy(0x1001)), Some(0x1001));
+        // unknown indexes fall back to the largest type
+        assert_eq!(chosen(policy(0x2000)), Some(0x1002));
+        assert_eq!(chosen(DuplicatePolicy::PdbIndex(HashMap::new())), Some(0x1002));
+    }
+
+    #[test]
+    fn unique_name() {
+        let policy = |name: &str| DuplicatePolicy::UniqueName(HashMap::from([("Foo".to_string(), name.to_string())]));
+        assert_eq!(chosen(policy(".?AUFoo@a@@")), Some(0x1003));
+        assert_eq!(chosen(policy(".?AUFoo@b@@")), Some(0x1002));
+    }
+
+    #[test]
+    fn error() {
+        let mut arena = arena();
+        match arena.set_duplicate_policy(DuplicatePolicy::Error) {
+            Err(Error::DuplicateType { name, candidates }) => {
+                assert_eq!(name, "Foo");
+                assert_eq!(candidates, [Some(0x1000), Some(0x1001), Some(0x1002), Some(0x1003)]);
+            }
+            res => panic!("expected DuplicateType, got {:?}", res),
+        }
+
+        // opaque types don't conflict
+        let mut arena = Arena::new(MachineType::Amd64);
+        arena.insert_class(class(0, 0, 0x1000, true), 0x1000);
+        arena.insert_class(class(8, 1, 0x1001, false), 0x1001);
+        arena.set_duplicate_policy(DuplicatePolicy::Error).unwrap();
+        assert_eq!(arena.origin(arena.type_names()["Foo"]).pdb_index(), Some(0x1001));
+    }
+}
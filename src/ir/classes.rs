@@ -1,8 +1,8 @@
 use std::collections::VecDeque;
 use std::cmp;
-use pdb::{self, FieldAttributes, TypeProperties, ClassType, TypeData, BaseClassType, MemberType, PointerType, BitfieldType, ArrayType, ModifierType, VirtualBaseClassType, Indirection};
-use crate::ir::{ClassIndex, Name, ClassKind, PrimitiveKind, EnumIndex, UnionIndex, Converter, Size, Union, Arena};
-use crate::{Alignment, Result};
+use pdb::{self, FieldAttributes, TypeProperties, ClassType, TypeData, BaseClassType, MemberType, PointerType, BitfieldType, ArrayType, ModifierType, VirtualBaseClassType, Indirection, ProcedureType, MemberFunctionType, MethodType, MethodListEntry, StaticMemberType};
+use crate::ir::{ClassIndex, Name, ClassKind, PrimitiveKind, EnumIndex, UnionIndex, Converter, Size, Align, Union, Arena, Origin};
+use crate::{Alignment, Error, Result};
 
 #[derive(Debug)]
 pub struct Class {
@@ -10,41 +10,95 @@ pub struct Class {
     pub kind: ClassKind,
     pub members: Vec<ClassMember>,
     pub properties: Properties,
-    //pub derived_from: Option<ClassIndex>,
-    // TODO: vtable_shape
+    /// Classes derived from this one, only listed by some compilers.
+    pub derived_from: Vec<ClassIndex>,
+    /// Types declared inside of the class, including typedefs.
+    pub nested_types: Vec<NestedType>,
+    pub static_members: Vec<StaticMember>,
+    /// All virtual methods including the inherited ones, ordered by vtable and slot.
+    pub virtual_methods: Vec<VirtualMethod>,
+    pub vtable_shape: Option<VtableShape>,
     pub size: usize,
     pub alignment: Alignment,
+    /// The type couldn't be converted and only its name and size are known.
+    pub opaque: bool,
+    pub origin: Origin,
 }
 
 impl Class {
     pub fn from(converter: &mut Converter, class: ClassType) -> Result<Class> {
         log::trace!("Class::from {:?}", class);
-        let ClassType { name, kind, fields, properties, derived_from, size, ..} = class;
-        assert_eq!(derived_from, None);
-        assert_ne!(kind, ClassKind::Interface);
+        let ClassType { name, kind, fields, properties, derived_from, vtable_shape, size, ..} = class;
         let mut members = VecDeque::new();
-        if let Some(field) = fields {
-            match converter.pdb_type(field) {
+        let mut methods = Vec::new();
+        let mut static_members = Vec::new();
+        let mut nested = Vec::new();
+        let mut virtual_bases = Vec::new();
+        if let Some(field_list) = fields {
+            match converter.pdb_type(field_list)? {
                 TypeData::FieldList(list) => {
                     let mut peekable = list.fields.into_iter().peekable();
                     let mut last_offset = 0;
                     while let Some(field) = peekable.next() {
-                        let max_size = peekable.peek().map(|t| match t {
-                            TypeData::Member(field) => field.offset as usize - last_offset,
-                            _ => usize::MAX,
-                        }).unwrap_or(size as usize - last_offset);
-                        if let Ok(Some(member)) = ClassMember::from(converter, field, max_size) {
-                            last_offset = member.offset();
-                            members.push_back(member);
+                        let offset = match &field {
+                            TypeData::Member(field) => field.offset as usize,
+                            _ => last_offset,
+                        };
+                        let max_size = max_size(peekable.peek(), offset, size as usize);
+                        match field {
+                            TypeData::Method(method) => {
+                                let MethodType { attributes, method_type, vtable_offset, name } = method;
+                                methods.push((name.into(), attributes, method_type, vtable_offset));
+                                continue;
+                            }
+                            TypeData::OverloadedMethod(method) => {
+                                let list = match converter.pdb_type(method.method_list)? {
+                                    TypeData::MethodList(list) => list,
+                                    t => return Err(converter.unexpected_type(method.method_list, &t, "method list")),
+                                };
+                                let name: Name = method.name.into();
+                                for entry in list.methods {
+                                    let MethodListEntry { attributes, method_type, vtable_offset } = entry;
+                                    methods.push((name.clone(), attributes, method_type, vtable_offset));
+                                }
+                                continue;
+                            }
+                            TypeData::StaticMember(member) => {
+                                static_members.push(StaticMember::from(converter, member)?);
+                                continue;
+                            }
+                            TypeData::Nested(nested_type) => {
+                                nested.push(nested_type);
+                                continue;
+                            }
+                            _ => {}
+                        }
+                        match ClassMember::from(converter, field_list, field, max_size)? {
+                            // virtual bases aren't stored in place, only their virtual base pointer
+                            Some(ClassMember::VirtualBaseClass(base)) => virtual_bases.push(base),
+                            Some(member) => {
+                                last_offset = member.offset();
+                                members.push_back(member);
+                            }
+                            None => {}
                         }
                     }
                 }
-                t => unreachable!("Not a FieldList {:?}", t)
+                t => return Err(converter.unexpected_type(field_list, &t, "field list")),
             }
         }
-        let name: Name = name.into();
-        let members = Class::transform_bitfields(&name, members);
-        let members = Class::transform_unions(converter.arena, &name, members);
+        // after the members, such that anonymous types are named after the field using them
+        let nested_types = NestedType::from_all(converter, nested);
+        let name = converter.type_name(name);
+        let vtable_shape = vtable_shape.and_then(|idx| converter.vtable_shape(idx));
+        let derived_from = Class::derived_from(converter, derived_from)?;
+        Class::insert_virtual_base_pointers(converter.arena, &mut members, &virtual_bases);
+        let members = Class::transform_bitfields(&name, members)?;
+        let origin = Origin::Pdb(converter.current_index());
+        let mut members = Class::transform_unions(converter.arena, &name, origin, members);
+        Class::layout_virtual_bases(converter, &name, &members, &mut virtual_bases, size as usize)?;
+        members.extend(virtual_bases.into_iter().map(ClassMember::VirtualBaseClass));
+        let virtual_methods = Class::virtual_methods(converter, &members, methods)?;
         let size = if size == 0 && !members.is_empty() {
             let last = members.last().unwrap();
             last.offset() + last.size(&converter.arena)
@@ -56,20 +110,87 @@ impl Class {
             kind,
             members,
             properties: properties.into(),
+            derived_from,
+            nested_types,
+            static_members,
+            virtual_methods,
+            vtable_shape,
             size,
             alignment: Alignment::None,
+            opaque: false,
+            origin,
         })
     }
 
-    pub fn check_offsets(&self, arena: &Arena) {
+    /// Collects the virtual methods of the base classes and merges the declared methods into
+    /// them, either as overrides or as new slots of the primary vtable.
+    fn virtual_methods(converter: &mut Converter, members: &[ClassMember], methods: Vec<(Name, FieldAttributes, pdb::TypeIndex, Option<u32>)>) -> Result<Vec<VirtualMethod>> {
+        let mut virtual_methods = Vec::new();
+        for member in members {
+            let (base_class, offset) = match member {
+                ClassMember::BaseClass(base) => (base.base_class, base.offset),
+                ClassMember::VirtualBaseClass(base) if base.offset_known => (base.base_class, base.offset),
+                _ => continue,
+            };
+            let base = &converter.arena[base_class];
+            // the vtables of the virtual bases of the base are those of this class' virtual bases
+            let non_virtual_size = base.non_virtual_size(converter.arena);
+            for method in base.virtual_methods.iter().filter(|method| method.vtable_offset < non_virtual_size) {
+                virtual_methods.push(VirtualMethod {
+                    vtable_offset: method.vtable_offset + offset,
+                    inherited: true,
+                    ..method.clone()
+                });
+            }
+        }
+        let pointer_size = converter.arena.pointer_size();
+        for (name, attributes, method_type, vtable_offset) in methods {
+            if !(attributes.is_virtual() || attributes.is_pure_virtual() || attributes.is_intro_virtual()) {
+                continue;
+            }
+            let signature = Signature::from(converter, method_type)?;
+            if let Some(vtable_offset) = vtable_offset {
+                let slot = vtable_offset as usize / pointer_size;
+                let mut method = VirtualMethod { name, signature, vtable_offset: 0, slot, intro: true, inherited: false };
+                // The compiler generated `__vecDelDtor` is introduced in the slot of the
+                // destructor again by every class.
+                match virtual_methods.iter_mut().find(|m| m.vtable_offset == 0 && m.slot == slot) {
+                    Some(existing) => {
+                        method.intro = !existing.inherited;
+                        *existing = method;
+                    }
+                    None => virtual_methods.push(method),
+                }
+                continue;
+            }
+            // an override replaces the method in every vtable containing it
+            let overridden: Vec<_> = virtual_methods.iter_mut()
+                .filter(|method| method.inherited && method.is_overridden_by(&name, &signature))
+                .collect();
+            if overridden.is_empty() {
+                log::debug!("{} doesn't override any method of a base class", name.name);
+            }
+            for method in overridden {
+                method.name = name.clone();
+                method.signature = signature.clone();
+                method.intro = false;
+                method.inherited = false;
+            }
+        }
+        virtual_methods.sort_by_key(|method| (method.vtable_offset, method.slot));
+        Ok(virtual_methods)
+    }
+
+    /// Checks that every member directly follows the previous one.
+    pub fn check_offsets(&self, arena: &Arena) -> Result<()> {
         let mut size = 0;
         for member in &self.members {
-            eprintln!("{:?}", member);
-            eprintln!("{}", size);
-            eprintln!("{}", member.size(arena));
-            assert_eq!(size, member.offset(), "{:?}", member);
+            if size != member.offset() {
+                return Err(Error::UnexpectedLayout(format!("member of {} at {:#x} instead of {:#x}: {:?}", self.name.name, member.offset(), size, member)));
+            }
             size += member.size(arena);
         }
+        Ok(())
     }
 
     /// Converts inline-lying unions into actual unions
@@ -99,7 +220,8 @@ impl Class {
     //
     // To generate rust types, we need to detect these unions and create new types for them.
     // For simplification, for each union field, we create a new struct.
-    fn transform_unions(arena: &mut Arena, name: &Name, mut members: VecDeque<ClassMember>) -> Vec<ClassMember> {
+    fn transform_unions(arena: &mut Arena, name: &Name, origin: Origin, mut members: VecDeque<ClassMember>) -> Vec<ClassMember> {
+        let origin = origin.synthesized();
         let mut res = Vec::with_capacity(members.len());
         let mut union_number = 0;
 
@@ -129,9 +251,17 @@ impl Class {
                             kind: ClassKind::Struct,
                             members: union_struct,
                             properties: Properties::default(),
+                            nested_types: Vec::new(),
+                            static_members: Vec::new(),
+                            virtual_methods: Vec::new(),
+                            vtable_shape: None,
+                            derived_from: Vec::new(),
                             size,
                             alignment: Alignment::None,
+                            opaque: false,
+                            origin,
                         })),
+                        alias: None,
                         max_size: size,
                     });
                 }
@@ -162,9 +292,17 @@ impl Class {
                         kind: ClassKind::Struct,
                         members: union_struct,
                         properties: Properties::default(),
+                        nested_types: Vec::new(),
+                        static_members: Vec::new(),
+                        virtual_methods: Vec::new(),
+                        vtable_shape: None,
+                        derived_from: Vec::new(),
                         size: max_size,
                         alignment: Alignment::None,
+                        opaque: false,
+                        origin,
                     })),
+                    alias: None,
                     max_size,
                 });
                 // We have created all union-field-structs. Now we create the actual union
@@ -179,10 +317,14 @@ impl Class {
                         name: format!("{}_Union{}", name.ident, union_number).into(),
                         fields: union_fields,
                         properties: Properties::default(),
+                        nested_types: Vec::new(),
                         size: max_size,
                         count,
                         alignment: Alignment::None,
+                        opaque: false,
+                        origin,
                     })),
+                    alias: None,
                     max_size,
                 }));
             } else {
@@ -192,7 +334,122 @@ impl Class {
         res
     }
 
-    fn transform_bitfields(name: &Name, mut members: VecDeque<ClassMember>) -> VecDeque<ClassMember> {
+    /// Inserts the virtual base pointers of the class, unless they are shared with a base class.
+    fn insert_virtual_base_pointers(arena: &Arena, members: &mut VecDeque<ClassMember>, virtual_bases: &[VirtualBaseClass]) {
+        let mut existing: Vec<_> = members.iter()
+            .filter_map(|member| match member {
+                ClassMember::BaseClass(base) => Some(base),
+                _ => None,
+            })
+            .flat_map(|base| arena[base.base_class].virtual_base_pointer_offsets(arena).into_iter()
+                .map(move |offset| base.offset + offset))
+            .collect();
+        for base in virtual_bases {
+            let offset = base.base_pointer_offset;
+            if existing.contains(&offset) {
+                continue;
+            }
+            existing.push(offset);
+            // the vtable pointer always comes first
+            let position = members.iter()
+                .position(|member| !matches!(member, ClassMember::Vtable) && member.offset() > offset)
+                .unwrap_or(members.len());
+            members.insert(position, ClassMember::VirtualBasePointer(VirtualBasePointer {
+                offset,
+                kind: base.base_pointer.clone(),
+            }));
+        }
+    }
+
+    /// Offsets of all virtual base pointers, including those of base classes.
+    pub fn virtual_base_pointer_offsets(&self, arena: &Arena) -> Vec<usize> {
+        let mut offsets = Vec::new();
+        for member in &self.members {
+            match member {
+                ClassMember::VirtualBasePointer(pointer) => offsets.push(pointer.offset),
+                ClassMember::BaseClass(base) => offsets.extend(arena[base.base_class]
+                    .virtual_base_pointer_offsets(arena).into_iter()
+                    .map(|offset| base.offset + offset)),
+                _ => {}
+            }
+        }
+        offsets
+    }
+
+    /// Places the virtual bases behind the non-virtual part of the class like MSVC does, in the
+    /// order of their vbtable entries, which lists the virtual bases of a base before the base.
+    ///
+    /// The pdb doesn't contain the offsets of virtual bases, only the vbtable does. If the
+    /// bases don't fill the class up to its size, a `vtordisp` is assumed in front of each
+    /// base with a vtable. If that doesn't fit either, the offsets are left unknown and the
+    /// virtual bases are only written as opaque bytes, unless the conversion is strict.
+    fn layout_virtual_bases(converter: &Converter, name: &Name, members: &[ClassMember], virtual_bases: &mut [VirtualBaseClass], size: usize) -> Result<()> {
+        if virtual_bases.is_empty() {
+            return Ok(());
+        }
+        let arena = &*converter.arena;
+        virtual_bases.sort_by_key(|base| base.virtual_base_offset);
+        let non_virtual_align = non_virtual_align(members, arena);
+        let start = align_up(non_virtual_end(members, arena), non_virtual_align);
+        let place = |virtual_bases: &mut [VirtualBaseClass], vtordisp: bool| {
+            let mut offset = start;
+            let mut align = non_virtual_align;
+            for base in virtual_bases.iter_mut() {
+                let class = &arena[base.base_class];
+                base.vtordisp = vtordisp && !class.virtual_methods.is_empty();
+                if base.vtordisp {
+                    offset += 4;
+                }
+                offset = align_up(offset, class.align(arena));
+                base.offset = offset;
+                offset += class.non_virtual_size(arena);
+                align = align.max(class.align(arena));
+            }
+            align_up(offset, align)
+        };
+        if size == 0 || place(virtual_bases, false) == size || place(virtual_bases, true) == size {
+            return Ok(());
+        }
+        if converter.strict_virtual_bases() {
+            return Err(Error::VirtualBaseLayout(name.name.clone()));
+        }
+        log::warn!("can't determine the offsets of the virtual bases of {}, writing them as opaque bytes", name.name);
+        for base in virtual_bases {
+            base.offset = start;
+            base.vtordisp = false;
+            base.offset_known = false;
+        }
+        Ok(())
+    }
+
+    /// End of the last member which isn't a virtual base.
+    pub fn non_virtual_end(&self, arena: &Arena) -> usize {
+        non_virtual_end(&self.members, arena)
+    }
+
+    /// Size of the class without its virtual bases, which is the space it takes as base class.
+    pub fn non_virtual_size(&self, arena: &Arena) -> usize {
+        if !self.members.iter().any(|member| matches!(member, ClassMember::VirtualBaseClass(_))) {
+            return self.size;
+        }
+        align_up(self.non_virtual_end(arena), non_virtual_align(&self.members, arena))
+    }
+
+    /// Converts the `LF_DERIVED` list, inserting the derived classes as stubs.
+    fn derived_from(converter: &mut Converter, derived_list: Option<pdb::TypeIndex>) -> Result<Vec<ClassIndex>> {
+        let mut derived = Vec::new();
+        for idx in derived_list.map(|idx| converter.derived_list(idx)).unwrap_or_default() {
+            converter.set_pointee(true);
+            let kind = ClassFieldKind::from(converter, idx);
+            converter.set_pointee(false);
+            if let ClassFieldKind::Class(class) = kind? {
+                derived.push(class);
+            }
+        }
+        Ok(derived)
+    }
+
+    fn transform_bitfields(name: &Name, mut members: VecDeque<ClassMember>) -> Result<VecDeque<ClassMember>> {
         let mut res = VecDeque::with_capacity(members.len());
         let mut bitfield_number = 0;
         let mut pos = usize::max_value();
@@ -200,8 +457,11 @@ impl Class {
         let mut fields = Vec::new();
         while let Some(member) = members.pop_front() {
             if let ClassMember::Field(ClassField { offset: offs, kind: ClassFieldKind::Bitfield(mut b), .. }) = member {
-                assert_eq!(b.fields.len(), 1);
-                let field = b.fields.pop().unwrap();
+                // each bitfield record describes a single field
+                let field = match (b.fields.pop(), b.fields.is_empty()) {
+                    (Some(field), true) => field,
+                    _ => return Err(Error::UnexpectedLayout(format!("bitfield member of {} at {:#x} doesn't have exactly one field", name.name, offs))),
+                };
                 if field.position < pos && !fields.is_empty() {
                     // new bitfield after bitfield, need to finish old one
                     let max_size = fields.iter().map(|f: &BitfieldField| f.length).sum();
@@ -212,6 +472,7 @@ impl Class {
                         kind: ClassFieldKind::Bitfield(Bitfield {
                             fields,
                         }),
+                        alias: None,
                         max_size,
                     }));
                     bitfield_number += 1;
@@ -234,6 +495,7 @@ impl Class {
                         kind: ClassFieldKind::Bitfield(Bitfield {
                             fields,
                         }),
+                        alias: None,
                         max_size,
                     }));
                     pos = usize::max_value();
@@ -243,20 +505,160 @@ impl Class {
                 res.push_back(member);
             }
         }
+        Ok(res)
+    }
+}
+
+/// A type declared inside of a class or union.
+#[derive(Debug)]
+pub struct NestedType {
+    /// The name within the enclosing type.
+    pub name: Name,
+    pub kind: ClassFieldKind,
+}
+
+impl NestedType {
+    pub fn from(converter: &mut Converter, nested: pdb::NestedType) -> Result<NestedType> {
+        let pdb::NestedType { nested_type, name, .. } = nested;
+        // the declaration doesn't need the definition
+        converter.set_pointee(true);
+        let kind = ClassFieldKind::from(converter, nested_type);
+        converter.set_pointee(false);
+        Ok(NestedType {
+            name: name.into(),
+            kind: kind?,
+        })
+    }
+
+    /// Converts the nested types of a class or union, skipping those which can't be converted
+    /// as they don't affect its layout.
+    ///
+    /// In lenient mode, the failures are recorded in the `Report`.
+    pub fn from_all(converter: &mut Converter, nested: Vec<pdb::NestedType>) -> Vec<NestedType> {
+        let mut res = Vec::new();
+        for nested_type in nested {
+            let (name, idx) = (nested_type.name.to_string().into_owned(), nested_type.nested_type);
+            match NestedType::from(converter, nested_type) {
+                Ok(nested_type) => res.push(nested_type),
+                Err(e) if converter.lenient => converter.record_failure(name, idx, e),
+                Err(e) => log::warn!("skipping nested type {name}: {e}"),
+            }
+        }
         res
     }
 }
 
+#[derive(Debug)]
+pub struct StaticMember {
+    pub attributes: Attributes,
+    pub name: Name,
+    pub kind: ClassFieldKind,
+    /// Address of the variable relative to the image base, if the pdb contains a symbol for it.
+    pub rva: Option<u32>,
+}
+
+impl StaticMember {
+    pub fn from(converter: &mut Converter, member: StaticMemberType) -> Result<StaticMember> {
+        let StaticMemberType { attributes, field_type, name } = member;
+        converter.set_field(Some(name.to_string().into_owned()));
+        let kind = ClassFieldKind::from(converter, field_type);
+        converter.set_field(None);
+        Ok(StaticMember {
+            attributes: attributes.into(),
+            name: name.into(),
+            kind: kind?,
+            rva: None,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct VirtualMethod {
+    pub name: Name,
+    pub signature: Signature,
+    /// Offset of the vtable pointer whose vtable contains this method.
+    pub vtable_offset: usize,
+    /// Index of the method within its vtable.
+    pub slot: usize,
+    /// Whether the method introduces its slot instead of overriding a method of a base class.
+    pub intro: bool,
+    /// Whether the method is inherited from a base class without being overridden.
+    pub inherited: bool,
+}
+
+impl VirtualMethod {
+    fn is_overridden_by(&self, name: &Name, signature: &Signature) -> bool {
+        // destructors override each other despite their different names
+        let is_destructor = |name: &Name| name.name.starts_with('~');
+        let same_name = self.name.name == name.name || (is_destructor(&self.name) && is_destructor(name));
+        same_name && self.signature.arguments == signature.arguments
+            && self.signature.is_const() == signature.is_const()
+    }
+}
+
+/// An `LF_VTSHAPE` record, describing the entries of a vtable.
+#[derive(Debug, Clone)]
+pub struct VtableShape {
+    pub slots: Vec<VtableSlotKind>,
+}
+
+/// A `CV_VTS_desc_e`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum VtableSlotKind {
+    Near,
+    Far,
+    Thin,
+    Outer,
+    Meta,
+    Near32,
+    Far32,
+    Unknown(u8),
+}
+
+impl From<u8> for VtableSlotKind {
+    fn from(raw: u8) -> VtableSlotKind {
+        match raw {
+            0 => VtableSlotKind::Near,
+            1 => VtableSlotKind::Far,
+            2 => VtableSlotKind::Thin,
+            3 => VtableSlotKind::Outer,
+            4 => VtableSlotKind::Meta,
+            5 => VtableSlotKind::Near32,
+            6 => VtableSlotKind::Far32,
+            raw => VtableSlotKind::Unknown(raw),
+        }
+    }
+}
+
+fn non_virtual_end(members: &[ClassMember], arena: &Arena) -> usize {
+    members.iter()
+        .filter(|member| !matches!(member, ClassMember::VirtualBaseClass(_)))
+        .map(|member| member.offset() + member.size(arena))
+        .max().unwrap_or(0)
+}
+
+fn non_virtual_align(members: &[ClassMember], arena: &Arena) -> usize {
+    members.iter()
+        .filter(|member| !matches!(member, ClassMember::VirtualBaseClass(_)))
+        .map(|member| member.align(arena))
+        .max().unwrap_or(1)
+}
+
+fn align_up(offset: usize, align: usize) -> usize {
+    offset.div_ceil(align) * align
+}
+
 #[derive(Debug)]
 pub enum ClassMember {
     Vtable,
+    VirtualBasePointer(VirtualBasePointer),
     BaseClass(BaseClass),
     VirtualBaseClass(VirtualBaseClass),
     Field(ClassField),
 }
 
 impl ClassMember {
-    pub fn from(converter: &mut Converter, typ: TypeData, max_size: usize) -> Result<Option<ClassMember>> {
+    pub fn from(converter: &mut Converter, field_list: pdb::TypeIndex, typ: TypeData, max_size: usize) -> Result<Option<ClassMember>> {
         log::trace!("ClassMember::from {:?}", typ);
         Ok(match typ {
             TypeData::BaseClass(class) => Some(ClassMember::BaseClass(BaseClass::from(converter, class)?)),
@@ -266,9 +668,7 @@ impl ClassMember {
             TypeData::MemberFunction(_) => None,
             TypeData::OverloadedMethod(_) => None,
             TypeData::Method(_) => None,
-            TypeData::Nested(_) => None,
-            TypeData::StaticMember(_) => None,
-            t => unimplemented!("ClassMember: {:?}", t)
+            t => return Err(converter.unexpected_type(field_list, &t, "class member")),
         })
     }
 
@@ -276,8 +676,9 @@ impl ClassMember {
         match self {
             // please be a nice compiler
             ClassMember::Vtable => 0,
+            ClassMember::VirtualBasePointer(pointer) => pointer.offset,
             ClassMember::BaseClass(base) => base.offset,
-            ClassMember::VirtualBaseClass(base) => base.base_pointer_offset,
+            ClassMember::VirtualBaseClass(base) => base.offset,
             ClassMember::Field(field) => field.offset,
         }
     }
@@ -305,14 +706,34 @@ impl BaseClass {
     }
 }
 
+/// The pointer to the vbtable, which contains the offsets of the virtual bases relative to
+/// the pointer.
+#[derive(Debug)]
+pub struct VirtualBasePointer {
+    pub offset: usize,
+    pub kind: ClassFieldKind,
+}
+
 #[derive(Debug)]
 pub struct VirtualBaseClass {
+    /// `false` for virtual bases of base classes.
     pub direct: bool,
     pub attributes: Attributes,
     pub base_class: ClassIndex,
-    // TODO: base_pointer
+    /// Type of the virtual base pointer.
+    pub base_pointer: ClassFieldKind,
+    /// Offset of the virtual base pointer used to find the base, which may belong to a base class.
     pub base_pointer_offset: usize,
+    /// Index of the entry of the vbtable which contains the offset of the base.
     pub virtual_base_offset: usize,
+    /// Offset of the base within the class, behind all non-virtual members.
+    pub offset: usize,
+    /// The base is preceded by a 4 byte `vtordisp` field, which MSVC adds in front of virtual
+    /// bases with a vtable in some cases.
+    pub vtordisp: bool,
+    /// `false` if the virtual bases don't fit into the class as expected. Then `offset` is the
+    /// start of the virtual bases and they are only written as opaque bytes.
+    pub offset_known: bool,
 }
 
 impl VirtualBaseClass {
@@ -321,24 +742,44 @@ impl VirtualBaseClass {
         let VirtualBaseClassType { direct, attributes, base_class, base_pointer,
             base_pointer_offset, virtual_base_offset }  = class;
         let base_class = converter.convert_class(base_class)?;
+        let base_pointer = ClassFieldKind::from(converter, base_pointer)?;
         Ok(VirtualBaseClass {
             direct,
             attributes: attributes.into(),
             base_class,
+            base_pointer,
             base_pointer_offset: base_pointer_offset as usize,
             virtual_base_offset: virtual_base_offset as usize,
+            // set once all members are known
+            offset: 0,
+            vtordisp: false,
+            offset_known: true,
         })
     }
 }
 
+/// The space between a field and the following one, `usize::MAX` if unknown or if the following
+/// field overlaps with it.
+pub(in crate::ir) fn max_size(next: Option<&TypeData>, offset: usize, size: usize) -> usize {
+    match next {
+        Some(TypeData::Member(next)) if next.offset as usize > offset => next.offset as usize - offset,
+        Some(_) => usize::MAX,
+        None => size.saturating_sub(offset),
+    }
+}
+
 #[derive(Debug)]
 pub struct ClassField {
     pub attributes: Attributes,
     pub name: Name,
     pub offset: usize,
     pub kind: ClassFieldKind,
+    /// Name of the typedef the type of this field refers to, e.g. `LPSTR` for a `char *`.
+    /// Fields of classes, unions and enums always use the name of their type.
+    pub alias: Option<String>,
     /// The maximum size this field can have (difference between its offset and the offset
     /// of the following field or the size of the class if this field is the last field).
+    /// `usize::MAX` if unknown.
     pub max_size: usize,
 }
 
@@ -346,18 +787,32 @@ impl ClassField {
     pub fn from(converter: &mut Converter, field: MemberType, max_size: usize) -> Result<ClassField> {
         log::trace!("ClassField::from {:?}", field);
         let MemberType { attributes, name, offset, field_type, .. } = field;
-        let kind = ClassFieldKind::from(converter, field_type)?;
+        converter.set_field(Some(name.to_string().into_owned()));
+        let kind = ClassFieldKind::from(converter, field_type);
+        converter.set_field(None);
+        let kind = kind?;
+        let alias = match kind {
+            ClassFieldKind::Class(_) | ClassFieldKind::Union(_) | ClassFieldKind::Enum(_) => None,
+            _ => converter.typedef(field_type),
+        };
+        if let ClassFieldKind::Array(array) = &kind {
+            let size = array.size(converter.arena);
+            if max_size != usize::MAX && size > max_size {
+                log::warn!("array {} of {:#x} bytes exceeds the {:#x} bytes up to the next field", name, size, max_size);
+            }
+        }
         Ok(ClassField {
             attributes: attributes.into(),
             name: name.into(),
             offset: offset as usize,
             kind,
+            alias,
             max_size,
         })
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum ClassFieldKind {
     Primitive(PrimitiveKind),
     Enum(EnumIndex),
@@ -367,17 +822,14 @@ pub enum ClassFieldKind {
     Union(UnionIndex),
     Array(Box<Array>),
     Modifier(Box<Modifier>),
-    // TODO: Arguments
-    Procedure,
-    // TODO: Arguments
-    MemberFunction,
-    // TODO: Arguments
-    Method,
+    Procedure(Box<Signature>),
+    MemberFunction(Box<Signature>),
+    Method(Box<Signature>),
 }
 
 impl ClassFieldKind {
     pub fn from(converter: &mut Converter, idx: pdb::TypeIndex) -> Result<ClassFieldKind> {
-        let typ = converter.pdb_type(idx);
+        let typ = converter.pdb_type(idx)?;
         Ok(match typ {
             TypeData::Primitive(kind) if kind.indirection == Indirection::None => ClassFieldKind::Primitive(kind.kind),
             TypeData::Primitive(kind) => ClassFieldKind::Pointer(Box::new(Pointer {
@@ -400,15 +852,15 @@ impl ClassFieldKind {
             TypeData::Union(_) => ClassFieldKind::Union(converter.convert_union(idx)?),
             TypeData::Array(array) => ClassFieldKind::Array(Box::new(Array::from(converter, array)?)),
             TypeData::Modifier(modifier) => ClassFieldKind::Modifier(Box::new(Modifier::from(converter, modifier)?)),
-            TypeData::Procedure(_) => ClassFieldKind::Procedure,
-            TypeData::MemberFunction(_) => ClassFieldKind::MemberFunction,
-            TypeData::Method(_) => ClassFieldKind::Method,
-            t => unimplemented!("ClassFieldKind: {:?}", t)
+            TypeData::Procedure(_) => ClassFieldKind::Procedure(Box::new(Signature::from(converter, idx)?)),
+            TypeData::MemberFunction(_) => ClassFieldKind::MemberFunction(Box::new(Signature::from(converter, idx)?)),
+            TypeData::Method(method) => ClassFieldKind::Method(Box::new(Signature::from(converter, method.method_type)?)),
+            t => return Err(converter.unexpected_type(idx, &t, "field type")),
         })
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Pointer {
     pub underlying: ClassFieldKind,
     pub typ: u8,
@@ -420,7 +872,10 @@ pub struct Pointer {
 impl Pointer {
     pub fn from(converter: &mut Converter, ptr: PointerType) -> Result<Pointer> {
         let PointerType { attributes, underlying_type } = ptr;
-        let underlying = ClassFieldKind::from(converter, underlying_type)?;
+        converter.set_pointee(true);
+        let underlying = ClassFieldKind::from(converter, underlying_type);
+        converter.set_pointee(false);
+        let underlying = underlying?;
         Ok(Pointer {
             underlying,
             typ: attributes.pointer_type(),
@@ -431,6 +886,96 @@ impl Pointer {
     }
 }
 
+/// The type of a procedure or member function.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Signature {
+    pub return_type: ClassFieldKind,
+    pub arguments: Vec<ClassFieldKind>,
+    pub calling_convention: CallingConvention,
+    /// The type of the `this` pointer, `None` for procedures and static member functions.
+    pub this_type: Option<ClassFieldKind>,
+    pub variadic: bool,
+}
+
+impl Signature {
+    /// Converts the `Procedure` or `MemberFunction` record at the given index.
+    pub fn from(converter: &mut Converter, idx: pdb::TypeIndex) -> Result<Signature> {
+        let (return_type, attributes, argument_list, this_type) = match converter.pdb_type(idx)? {
+            TypeData::Procedure(ProcedureType { return_type, attributes, argument_list, .. }) =>
+                (return_type, attributes, argument_list, None),
+            TypeData::MemberFunction(MemberFunctionType { return_type, attributes, argument_list, this_pointer_type, .. }) =>
+                (Some(return_type), attributes, argument_list, this_pointer_type),
+            t => return Err(converter.unexpected_type(idx, &t, "procedure or member function")),
+        };
+        // the types of the signature are used by value, even if the function is a pointee
+        converter.set_pointee(false);
+        let return_type = match return_type {
+            Some(return_type) => ClassFieldKind::from(converter, return_type)?,
+            None => ClassFieldKind::Primitive(PrimitiveKind::Void),
+        };
+        let mut arguments = match converter.pdb_type(argument_list)? {
+            TypeData::ArgumentList(list) => list.arguments,
+            t => return Err(converter.unexpected_type(argument_list, &t, "argument list")),
+        };
+        // variadic functions end with a `T_NOTYPE` argument
+        let variadic = arguments.last() == Some(&0);
+        if variadic {
+            arguments.pop();
+        }
+        let arguments = arguments.into_iter()
+            .map(|argument| ClassFieldKind::from(converter, argument))
+            .collect::<Result<_>>()?;
+        let this_type = this_type.map(|this_type| ClassFieldKind::from(converter, this_type)).transpose()?;
+        Ok(Signature {
+            return_type,
+            arguments,
+            calling_convention: CallingConvention::from(attributes.calling_convention()),
+            this_type,
+            variadic,
+        })
+    }
+
+    /// Whether this is a `const` member function, i.e. `this` points to a `const` object.
+    pub fn is_const(&self) -> bool {
+        match &self.this_type {
+            Some(ClassFieldKind::Pointer(pointer)) => matches!(&pointer.underlying, ClassFieldKind::Modifier(modifier) if modifier.constant),
+            _ => false,
+        }
+    }
+}
+
+/// The `CV_call_e` calling convention of a function, ignoring the distinction between near and far.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum CallingConvention {
+    Cdecl,
+    Pascal,
+    Fastcall,
+    Stdcall,
+    Syscall,
+    Thiscall,
+    Clrcall,
+    Inline,
+    Vectorcall,
+    Other(u8),
+}
+
+impl From<u8> for CallingConvention {
+    fn from(raw: u8) -> CallingConvention {
+        match raw {
+            0x00 | 0x01 => CallingConvention::Cdecl,
+            0x02 | 0x03 => CallingConvention::Pascal,
+            0x04 | 0x05 => CallingConvention::Fastcall,
+            0x07 | 0x08 => CallingConvention::Stdcall,
+            0x09 | 0x0a => CallingConvention::Syscall,
+            0x0b => CallingConvention::Thiscall,
+            0x16 => CallingConvention::Clrcall,
+            0x17 => CallingConvention::Inline,
+            0x18 => CallingConvention::Vectorcall,
+            raw => CallingConvention::Other(raw),
+        }
+    }
+}
+
 #[derive(Default, Debug)]
 pub struct Attributes {
     pub is_static: bool,
@@ -495,7 +1040,7 @@ impl From<TypeProperties> for Properties {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Bitfield {
     pub fields: Vec<BitfieldField>,
 }
@@ -510,13 +1055,13 @@ impl Bitfield {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum BitfieldUnderlying {
     Primitive(PrimitiveKind),
     Enum(EnumIndex),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct BitfieldField {
     pub underlying: BitfieldUnderlying,
     pub length: usize,
@@ -536,50 +1081,79 @@ impl BitfieldField {
     }
 
     fn underlying(converter: &mut Converter, underlying_type: pdb::TypeIndex) -> Result<BitfieldUnderlying> {
-        let typ = converter.pdb_type(underlying_type);
+        let typ = converter.pdb_type(underlying_type)?;
 
         Ok(match typ {
             TypeData::Primitive(primitive) => BitfieldUnderlying::Primitive(primitive.kind),
             TypeData::Enumeration(typ) =>
                 BitfieldUnderlying::Enum(converter.convert_enum(underlying_type)?),
             TypeData::Modifier(m) => BitfieldField::underlying(converter, m.underlying_type)?,
-            t => unimplemented!("Bitfield is {:?}", t),
+            t => return Err(converter.unexpected_type(underlying_type, &t, "bitfield underlying type")),
         })
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Array {
+    /// The type of the elements, never an array itself.
     pub element_type: ClassFieldKind,
-    // TODO: indexing_type
-    pub stride: Option<u32>,
+    /// The type of the array index, e.g. `unsigned long`.
+    pub indexing_type: PrimitiveKind,
+    /// The number of elements of each dimension, innermost first, e.g. `[4, 2]` for
+    /// `float[2][4]`.
     pub dimensions: Vec<usize>,
 }
 
 impl Array {
+    /// Converts an `LF_ARRAY` record.
+    ///
+    /// Multi-dimensional arrays are stored as nested records, whose dimensions are merged into
+    /// this one. The pdb specifies dimensions as byte sizes, each including the lower dimensions.
+    ///
+    /// Strided arrays whose stride differs from the size of their elements can't be expressed
+    /// as rust arrays and are rejected.
     pub fn from(converter: &mut Converter, array: ArrayType) -> Result<Array> {
-        let ArrayType { element_type, dimensions, stride, .. } = array;
-        let element_type = ClassFieldKind::from(converter, element_type)?;
-        let mut size_so_far = element_type.size(converter.arena);
-        let dimensions = if size_so_far == 0 {
-            // For some reason it's ok for types with an actual size to be zero-sized in the pdb.
-            dimensions.into_iter().map(|i| i as usize).collect()
-        } else {
-            dimensions.into_iter().map(|i| {
-                let dim = i as usize / size_so_far;
-                size_so_far = i as usize;
-                dim
-            }).collect()
+        let ArrayType { element_type: element_index, indexing_type, dimensions: sizes, stride } = array;
+        let indexing_type = match converter.pdb_type(indexing_type)? {
+            TypeData::Primitive(kind) if kind.indirection == Indirection::None => kind.kind,
+            t => return Err(converter.unexpected_type(indexing_type, &t, "array index")),
+        };
+        let element_type = ClassFieldKind::from(converter, element_index)?;
+        let element_size = element_type.size(converter.arena);
+        match stride {
+            Some(stride) if stride as usize != element_size => return Err(Error::Unimplemented(
+                format!("array with a stride of {:#x} bytes for elements of {:#x} bytes", stride, element_size))),
+            _ => {}
+        }
+        let (element_type, mut dimensions) = match element_type {
+            ClassFieldKind::Array(inner) => (inner.element_type, inner.dimensions),
+            element_type => (element_type, Vec::new()),
         };
+        let mut size_so_far = element_size;
+        for size in sizes {
+            let size = size as usize;
+            if size_so_far == 0 {
+                // zero-sized elements don't tell the number of elements
+                log::warn!("array of zero-sized {:?} with {:#x} bytes, assuming 1 byte per element", element_type, size);
+                dimensions.push(size);
+                size_so_far = size;
+                continue;
+            }
+            if !size.is_multiple_of(size_so_far) {
+                log::warn!("array dimension of {:#x} bytes isn't a multiple of its element size {:#x}", size, size_so_far);
+            }
+            dimensions.push(size / size_so_far);
+            size_so_far = size;
+        }
         Ok(Array {
             element_type,
-            stride,
+            indexing_type,
             dimensions,
         })
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Modifier {
     pub underlying: ClassFieldKind,
     pub constant: bool,
@@ -0,0 +1,86 @@
+use std::collections::HashMap;
+
+use crate::ir::{ClassFieldKind, Converter, EnumValue, split_scope};
+use crate::symbols;
+use crate::Result;
+
+/// A named constant, e.g. `static constexpr int Max = 5;`.
+#[derive(Debug)]
+pub struct Constant {
+    /// The qualified name.
+    pub name: String,
+    pub kind: ClassFieldKind,
+    pub value: EnumValue,
+}
+
+impl Constant {
+    /// The enclosing class or namespace, `""` for the global namespace, and the unqualified name.
+    pub fn split_name(&self) -> (&str, &str) {
+        let (scope, name) = split_scope(&self.name);
+        (scope.unwrap_or(""), name)
+    }
+}
+
+/// The constants of a pdb, whose types refer to types of its `Arena`.
+#[derive(Debug, Default)]
+pub struct Constants {
+    constants: Vec<Constant>,
+    /// The indices of the constants of each enclosing class or namespace.
+    scopes: HashMap<String, Vec<usize>>,
+}
+
+impl Constants {
+    pub fn push(&mut self, constant: Constant) {
+        let (scope, _) = constant.split_name();
+        self.scopes.entry(scope.to_string()).or_default().push(self.constants.len());
+        self.constants.push(constant);
+    }
+
+    /// The constants declared directly within the class or namespace, `""` for the global
+    /// namespace.
+    pub fn in_scope<'a>(&'a self, scope: &str) -> impl Iterator<Item = &'a Constant> + 'a {
+        self.scopes.get(scope).into_iter().flatten().map(move |&index| &self.constants[index])
+    }
+
+    /// All classes and namespaces containing constants, unordered.
+    pub fn scopes(&self) -> impl Iterator<Item = &str> + '_ {
+        self.scopes.keys().map(String::as_str)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Constant> + '_ {
+        self.constants.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.constants.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.constants.is_empty()
+    }
+}
+
+impl Converter<'_, '_> {
+    /// Converts the types of the constants.
+    ///
+    /// In lenient mode, constants whose type can't be converted are recorded in the `Report`
+    /// and left out.
+    pub(crate) fn convert_constants(&mut self, constants: &[symbols::Constant]) -> Result<Constants> {
+        let mut res = Constants::default();
+        for constant in constants {
+            self.set_field(Some(constant.name.clone()));
+            let kind = ClassFieldKind::from(self, constant.type_index);
+            self.set_field(None);
+            match kind {
+                Ok(kind) => res.push(Constant {
+                    name: constant.name.clone(),
+                    kind,
+                    value: constant.value,
+                }),
+                Err(e) if self.lenient => self.record_failure(constant.name.clone(), constant.type_index, e),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(res)
+    }
+}
@@ -1,98 +1,704 @@
-use std::collections::VecDeque;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
 
-use pdb::{self, FallibleIterator, TypeInformation, Error as PdbError, TypeData, TypeFinder};
+use pdb::{self, FallibleIterator, TypeInformation, Error as PdbError, TypeData, TypeFinder, RawString};
 
-use crate::ir::{Arena, Name, Class, TypeIndex, ClassIndex, EnumIndex, UnionIndex, Enum, Union};
-use crate::Result;
+use crate::ir::{Arena, Name, Class, TypeIndex, ClassIndex, EnumIndex, UnionIndex, Enum, Union, PrimitiveKind, Report, Failure, Phase, Progress, Origin, VtableShape};
+use crate::symbols::Udt;
+use crate::{Alignment, Error, MachineType, Result};
 
-pub struct Converter<'a, 't> {
+/// Type indexes below are primitive types, which don't have a type record.
+const FIRST_NON_PRIMITIVE: pdb::TypeIndex = 0x1000;
+
+/// Index of all types of a pdb, which is shared between all converters of the same pdb.
+pub struct TypeTable<'t> {
     finder: TypeFinder<'t>,
-    pdb_type_indexes: VecDeque<pdb::TypeIndex>,
-    pub(in crate::ir) arena: &'a mut Arena,
+    /// All types which are converted by `populate`, anonymous types last such that they get
+    /// their name from the field they are used in if possible.
+    pdb_type_indexes: Vec<pdb::TypeIndex>,
+    /// Maps forward references to the pdb index of their definition.
+    definitions: HashMap<pdb::TypeIndex, pdb::TypeIndex>,
+    /// Definition of every named type, used for conversion by name.
+    names: HashMap<String, pdb::TypeIndex>,
+    /// Names of the anonymous types which are used by fields.
+    anonymous_names: HashMap<pdb::TypeIndex, String>,
+    progress: Option<Arc<dyn Progress>>,
+    converted: AtomicUsize,
+    vtable_shapes: HashMap<pdb::TypeIndex, VtableShape>,
+    derived_lists: HashMap<pdb::TypeIndex, Vec<pdb::TypeIndex>>,
+    /// Name of the typedef of each non-primitive type which has exactly one.
+    typedefs: HashMap<pdb::TypeIndex, String>,
+    strict_virtual_bases: bool,
 }
 
-impl<'a, 't, 's: 't> Converter<'a, 't> {
-    pub fn new(info: &'t TypeInformation<'s>, arena: &'a mut Arena) -> Result<Converter<'a, 't>> {
+impl<'t, 's: 't> TypeTable<'t> {
+    /// `unique_names` contains the decorated names of the types which have one, they are used
+    /// to find the definition of forward references.
+    pub fn new(info: &'t TypeInformation<'s>, unique_names: &HashMap<pdb::TypeIndex, String>, progress: Option<Arc<dyn Progress>>) -> Result<TypeTable<'t>> {
         let mut finder = info.new_type_finder();
         let mut iter = info.iter();
         finder.update(&iter);
-        let mut pdb_type_indexes = VecDeque::new();
+        let mut pdb_type_indexes = Vec::new();
+        let mut anonymous_indexes = Vec::new();
+        let mut definitions = Definitions::default();
+        let mut indexed = 0;
         while let Some(typ) = iter.next()? {
             finder.update(&iter);
+            indexed += 1;
+            if let Some(progress) = &progress {
+                progress.update(Phase::Indexing, indexed, info.len());
+            }
             match typ.parse() {
                 Ok(t) => {
+                    definitions.add(typ.type_index(), &t, unique_names.get(&typ.type_index()));
                     if t.name().is_none() {
                         log::info!("ignore: {t:?}");
                         continue;
                     }
-                    let name = Name::from(t.name().unwrap());
-                    if name.starts_with('<') {
-                        // ignore anonymous types
-                        log::info!("ignore: {t:?}");
-                        continue;
+                    if is_anonymous(&t.name().unwrap().to_string()) {
+                        anonymous_indexes.push(typ.type_index());
+                    } else {
+                        pdb_type_indexes.push(typ.type_index());
                     }
-                    pdb_type_indexes.push_back(typ.type_index());
                 }
                 Err(PdbError::UnimplementedTypeKind(_)) => {},
                 Err(e) => Err(e)?,
             }
         }
-        Ok(Converter {
+        pdb_type_indexes.extend(anonymous_indexes);
+        let (definitions, names) = definitions.resolve();
+        let anonymous_names = anonymous_names(&finder, &pdb_type_indexes, &definitions);
+        Ok(TypeTable {
             finder,
             pdb_type_indexes,
-            arena,
+            definitions,
+            names,
+            anonymous_names,
+            progress,
+            converted: AtomicUsize::new(0),
+            vtable_shapes: HashMap::new(),
+            derived_lists: HashMap::new(),
+            typedefs: HashMap::new(),
+            strict_virtual_bases: false,
         })
     }
 
+    /// Sets the `LF_VTSHAPE` records, which the pdb crate can't parse.
+    pub fn set_vtable_shapes(&mut self, vtable_shapes: HashMap<pdb::TypeIndex, VtableShape>) {
+        self.vtable_shapes = vtable_shapes;
+    }
+
+    /// Sets the `LF_DERIVED` records, which the pdb crate can't parse.
+    pub fn set_derived_lists(&mut self, derived_lists: HashMap<pdb::TypeIndex, Vec<pdb::TypeIndex>>) {
+        self.derived_lists = derived_lists;
+    }
+
+    /// Sets the typedefs of the `S_UDT` symbols, which fields of the aliased types refer to.
+    /// Primitive types and types with several typedefs are left out because it's unknown which
+    /// name a field used.
+    pub(crate) fn set_typedefs(&mut self, udts: &[Udt]) {
+        let mut typedefs: HashMap<pdb::TypeIndex, Option<&str>> = HashMap::new();
+        for udt in udts.iter().filter(|udt| udt.type_index >= FIRST_NON_PRIMITIVE) {
+            typedefs.entry(udt.type_index)
+                .and_modify(|name| if *name != Some(udt.name.as_str()) { *name = None })
+                .or_insert(Some(&udt.name));
+        }
+        self.typedefs = typedefs.into_iter()
+            .filter_map(|(idx, name)| Some((idx, name?.to_string())))
+            .collect();
+    }
+
+    /// Fail to convert classes whose virtual bases can't be placed instead of leaving their
+    /// layout unknown, see `Options::strict_virtual_bases`.
+    pub fn set_strict_virtual_bases(&mut self, strict: bool) {
+        self.strict_virtual_bases = strict;
+    }
+
+    /// Reports that a type of `pdb_type_indexes` has been converted.
+    fn advance(&self) {
+        let progress = match &self.progress {
+            Some(progress) => progress,
+            None => return,
+        };
+        let converted = self.converted.fetch_add(1, Ordering::Relaxed) + 1;
+        progress.update(Phase::Converting, converted, self.pdb_type_indexes.len());
+    }
+
+    pub fn pdb_type_indexes(&self) -> &[pdb::TypeIndex] {
+        &self.pdb_type_indexes
+    }
+
+    /// Converts all types like `Converter::populate`, but distributed over `threads` threads.
+    ///
+    /// The types are split into chunks of a fixed size, each of which is converted into its
+    /// own arena. Those are merged in order, such that the result doesn't depend on the
+    /// number of threads and is the same as that of `Converter::populate`. The dependencies of
+    /// a chunk's types are converted again for every chunk using them.
+    pub fn populate_parallel(&self, machine_type: MachineType, threads: usize, lenient: bool) -> Result<(Arena, Report)> {
+        self.populate_chunks(machine_type, threads, lenient, CHUNK_SIZE)
+    }
+
+    fn populate_chunks(&self, machine_type: MachineType, threads: usize, lenient: bool, chunk_size: usize) -> Result<(Arena, Report)> {
+        let chunks: Vec<_> = self.pdb_type_indexes.chunks(chunk_size).collect();
+        let results: Vec<_> = chunks.iter().map(|_| Mutex::new(None)).collect();
+        let next_chunk = AtomicUsize::new(0);
+        thread::scope(|scope| {
+            for _ in 0..threads.max(1) {
+                scope.spawn(|| loop {
+                    let chunk = next_chunk.fetch_add(1, Ordering::Relaxed);
+                    if chunk >= chunks.len() {
+                        break;
+                    }
+                    let mut arena = Arena::new(machine_type);
+                    let mut converter = Converter::new(self, &mut arena);
+                    converter.set_lenient(lenient);
+                    let res = converter.populate_indexes(chunks[chunk]).map(|()| converter.into_report());
+                    *results[chunk].lock().unwrap() = Some(res.map(|report| (arena, report)));
+                });
+            }
+        });
+
+        let mut arena = Arena::new(machine_type);
+        let mut report = Report::default();
+        for result in results {
+            let (chunk_arena, chunk_report) = result.into_inner().unwrap().unwrap()?;
+            arena.merge(chunk_arena);
+            report.merge(chunk_report);
+        }
+        arena.choose_type_names();
+        Ok((arena, report))
+    }
+}
+
+/// Number of types converted by a single thread at once during parallel conversion.
+/// Changing this changes the order of the types in the resulting arena.
+const CHUNK_SIZE: usize = 4096;
+
+pub struct Converter<'a, 't> {
+    table: &'a TypeTable<'t>,
+    pub(in crate::ir) arena: &'a mut Arena,
+    /// Pdb indexes and names of the types currently being converted, innermost last.
+    stack: Vec<(pdb::TypeIndex, String)>,
+    /// Name of the field whose type is currently being converted.
+    field: Option<String>,
+    /// Whether the type which is converted next is the target of a pointer.
+    pointee: bool,
+    /// Types which have only been inserted as opaque stubs because they were only used
+    /// behind pointers so far.
+    stubs: HashSet<pdb::TypeIndex>,
+    pub(in crate::ir) lenient: bool,
+    report: Report,
+}
+
+impl<'a, 't> Converter<'a, 't> {
+    pub fn new(table: &'a TypeTable<'t>, arena: &'a mut Arena) -> Converter<'a, 't> {
+        Converter {
+            table,
+            arena,
+            stack: Vec::new(),
+            field: None,
+            pointee: false,
+            stubs: HashSet::new(),
+            lenient: false,
+            report: Report::default(),
+        }
+    }
+
+    /// In lenient mode, types which fail to convert are recorded in the `Report` and inserted
+    /// into the arena as opaque types instead of aborting the conversion.
+    pub fn set_lenient(&mut self, lenient: bool) {
+        self.lenient = lenient;
+    }
+
+    pub fn report(&self) -> &Report {
+        &self.report
+    }
+
+    pub fn into_report(self) -> Report {
+        self.report
+    }
+
+    /// Converts every type of the pdb.
     pub fn populate(&mut self) -> Result<()> {
-        while let Some(idx) = self.pdb_type_indexes.pop_front() {
-            self.convert(idx)?;
+        let table = self.table;
+        self.populate_indexes(table.pdb_type_indexes())?;
+        self.arena.choose_type_names();
+        Ok(())
+    }
+
+    fn populate_indexes(&mut self, indexes: &[pdb::TypeIndex]) -> Result<()> {
+        for &idx in indexes {
+            match self.convert(idx) {
+                Ok(_) => self.table.advance(),
+                // the type record itself is broken, so we can't even create an opaque type
+                Err(e) if self.lenient => {
+                    let name = self.pdb_type(idx).ok()
+                        .and_then(|t| t.name())
+                        .map(|name| name.to_string().into_owned())
+                        .unwrap_or_default();
+                    self.record_failure(name, idx, e);
+                    self.table.advance();
+                }
+                Err(e) => return Err(e),
+            }
         }
         Ok(())
     }
 
-    pub(in crate::ir) fn pdb_type(&self, idx: pdb::TypeIndex) -> TypeData<'t> {
-        self.finder.find(idx).unwrap().parse().unwrap()
+    /// Converts only the type with the given name and the types it depends on.
+    ///
+    /// Types which are only used behind pointers are inserted as opaque stubs, which are
+    /// converted completely once they are requested themselves or used by value.
+    pub fn convert_by_name(&mut self, name: &str) -> Result<TypeIndex> {
+        let index = match self.table.names.get(name) {
+            Some(&idx) => self.convert(idx)?,
+            None => return Err(Error::UnknownType(name.to_string())),
+        };
+        self.arena.choose_type_names();
+        Ok(index)
+    }
+
+    pub(in crate::ir) fn record_failure(&mut self, name: String, idx: pdb::TypeIndex, reason: Error) {
+        log::warn!("failed to convert {name}: {reason}");
+        self.report.failures.push(Failure {
+            name,
+            index: idx,
+            reason,
+            stack: self.stack.iter().map(|(_, name)| name.clone()).collect(),
+        });
+    }
+
+    pub(in crate::ir) fn vtable_shape(&self, idx: pdb::TypeIndex) -> Option<VtableShape> {
+        self.table.vtable_shapes.get(&idx).cloned()
+    }
+
+    pub(in crate::ir) fn derived_list(&self, idx: pdb::TypeIndex) -> Vec<pdb::TypeIndex> {
+        self.table.derived_lists.get(&idx).cloned().unwrap_or_default()
+    }
+
+    pub(in crate::ir) fn typedef(&self, idx: pdb::TypeIndex) -> Option<String> {
+        self.table.typedefs.get(&idx).cloned()
+    }
+
+    pub(in crate::ir) fn strict_virtual_bases(&self) -> bool {
+        self.table.strict_virtual_bases
+    }
+
+    pub(in crate::ir) fn pdb_type(&self, idx: pdb::TypeIndex) -> Result<TypeData<'t>> {
+        let typ = self.table.finder.find(idx).map_err(|source| Error::UnreadableType {
+            index: idx,
+            kind: "missing record".to_string(),
+            class: self.enclosing_type(),
+            source,
+        })?;
+        typ.parse().map_err(|source| Error::UnreadableType {
+            index: idx,
+            kind: format!("leaf {:#06x}", typ.raw_kind()),
+            class: self.enclosing_type(),
+            source,
+        })
+    }
+
+    /// Name of the innermost type currently being converted.
+    pub(in crate::ir) fn enclosing_type(&self) -> String {
+        self.stack.last().map(|(_, name)| name.clone()).unwrap_or_else(|| "<toplevel>".to_string())
+    }
+
+    /// Name of the type currently being converted.
+    /// Anonymous types get a name synthesized from the type and field they are used in.
+    pub(in crate::ir) fn type_name(&self, name: RawString) -> Name {
+        let name = name.to_string();
+        match self.stack.last() {
+            Some((_, current)) if is_anonymous(&name) => current.clone().into(),
+            _ => name.into_owned().into(),
+        }
+    }
+
+    /// Pdb index of the type currently being converted.
+    pub(in crate::ir) fn current_index(&self) -> pdb::TypeIndex {
+        self.stack.last().map(|&(idx, _)| idx).expect("no type is being converted")
+    }
+
+    /// Sets the name of the field whose type is converted next, which is used to name
+    /// anonymous types.
+    pub(in crate::ir) fn set_field(&mut self, field: Option<String>) {
+        self.field = field;
+    }
+
+    /// Marks the type which is converted next as target of a pointer, such that only a stub is
+    /// created for it.
+    pub(in crate::ir) fn set_pointee(&mut self, pointee: bool) {
+        self.pointee = pointee;
+    }
+
+    fn anonymous_name(&self, idx: pdb::TypeIndex, field: Option<String>) -> String {
+        if let Some(name) = self.table.anonymous_names.get(&idx) {
+            return name.clone();
+        }
+        match (self.stack.last(), field) {
+            (Some((_, parent)), Some(field)) => format!("{parent}__anon_{field}"),
+            (Some((_, parent)), None) => format!("{parent}__anon_{idx:x}"),
+            (None, _) => format!("__anon_{idx:x}"),
+        }
+    }
+
+    pub(in crate::ir) fn unexpected_type(&self, idx: pdb::TypeIndex, typ: &TypeData, expected: &'static str) -> Error {
+        Error::UnexpectedType {
+            index: idx,
+            kind: type_kind(typ),
+            expected,
+            class: self.enclosing_type(),
+        }
     }
 
     fn convert(&mut self, idx: pdb::TypeIndex) -> Result<TypeIndex> {
-        if let Some(&index) = self.arena.index_map().get(&idx) {
-            return Ok(index);
+        let field = self.field.take();
+        let pointee = std::mem::take(&mut self.pointee);
+        let idx = self.table.definitions.get(&idx).copied().unwrap_or(idx);
+        let existing = self.arena.index_map().get(&idx).copied();
+        match existing {
+            Some(index) if pointee || !self.stubs.contains(&idx) => return Ok(index),
+            _ => {}
         }
-        let typ = self.pdb_type(idx);
-        Ok(match typ {
-            TypeData::Class(class) => {
+        let typ = self.pdb_type(idx)?;
+        let (index, name) = match existing {
+            // upgrade the stub in place
+            Some(index) => {
+                self.stubs.remove(&idx);
+                (index, self.arena.name_of(index).name.clone())
+            }
+            None => {
+                let mut name = typ.name().map(|name| name.to_string().into_owned()).unwrap_or_default();
+                if is_anonymous(&name) {
+                    name = self.anonymous_name(idx, field);
+                }
+                // Reserve the index before converting the members, such that (indirectly)
+                // recursive types refer to it instead of being converted again.
+                let index = match self.insert_placeholder(idx, name.clone().into(), typ.clone()) {
+                    Some(index) => index,
+                    None => return Err(self.unexpected_type(idx, &typ, "class, enum or union")),
+                };
+                if pointee {
+                    self.stubs.insert(idx);
+                    return Ok(index);
+                }
+                (index, name)
+            }
+        };
+        self.stack.push((idx, name.clone()));
+        let res = self.convert_type(idx, index, typ);
+        self.stack.pop();
+        match res {
+            Ok(()) => Ok(index),
+            // the placeholder stays in the arena as opaque type
+            Err(e) if self.lenient => {
+                self.record_failure(name, idx, e);
+                Ok(index)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    fn convert_type(&mut self, idx: pdb::TypeIndex, index: TypeIndex, typ: TypeData) -> Result<()> {
+        match (index, typ) {
+            (TypeIndex::Class(index), TypeData::Class(class)) => {
                 let class = Class::from(self, class)?;
-                TypeIndex::Class(self.arena.insert_class(class, idx))
+                self.arena.replace_class(index, class);
             }
-            TypeData::Enumeration(e) => {
+            (TypeIndex::Enum(index), TypeData::Enumeration(e)) => {
                 let e = Enum::from(self, e)?;
-                TypeIndex::Enum(self.arena.insert_enum(e, idx))
+                self.arena.replace_enum(index, e);
             }
-            TypeData::Union(u) => {
+            (TypeIndex::Union(index), TypeData::Union(u)) => {
                 let u = Union::from(self, u)?;
-                TypeIndex::Union(self.arena.insert_union(u, idx))
+                self.arena.replace_union(index, u);
             }
-            _ => unimplemented!()
+            (_, t) => return Err(self.unexpected_type(idx, &t, "class, enum or union")),
+        }
+        Ok(())
+    }
+
+    /// Inserts a member-less opaque type of the same name and size, which is replaced once the
+    /// type is converted successfully.
+    fn insert_placeholder(&mut self, idx: pdb::TypeIndex, name: Name, typ: TypeData) -> Option<TypeIndex> {
+        Some(match typ {
+            TypeData::Class(class) => TypeIndex::Class(self.arena.insert_class(Class {
+                name,
+                kind: class.kind,
+                members: Vec::new(),
+                properties: class.properties.into(),
+                nested_types: Vec::new(),
+                static_members: Vec::new(),
+                virtual_methods: Vec::new(),
+                vtable_shape: None,
+                derived_from: Vec::new(),
+                size: class.size as usize,
+                alignment: Alignment::None,
+                opaque: true,
+                origin: Origin::Pdb(idx),
+            }, idx)),
+            TypeData::Enumeration(e) => {
+                let underlying = match self.pdb_type(e.underlying_type) {
+                    Ok(TypeData::Primitive(primitive)) => primitive.kind,
+                    _ => PrimitiveKind::I32,
+                };
+                TypeIndex::Enum(self.arena.insert_enum(Enum {
+                    name,
+                    underlying,
+                    variants: Vec::new(),
+                    properties: e.properties.into(),
+                    count: 0,
+                    alignment: Alignment::None,
+                    opaque: true,
+                    origin: Origin::Pdb(idx),
+                }, idx))
+            }
+            TypeData::Union(u) => TypeIndex::Union(self.arena.insert_union(Union {
+                name,
+                fields: Vec::new(),
+                properties: u.properties.into(),
+                nested_types: Vec::new(),
+                size: u.size as usize,
+                count: 0,
+                alignment: Alignment::None,
+                opaque: true,
+                origin: Origin::Pdb(idx),
+            }, idx)),
+            _ => return None,
         })
     }
 
+    fn unexpected_index(&self, idx: pdb::TypeIndex, index: TypeIndex, expected: &'static str) -> Error {
+        Error::UnexpectedType {
+            index: idx,
+            kind: match index {
+                TypeIndex::Class(_) => "class",
+                TypeIndex::Enum(_) => "enum",
+                TypeIndex::Union(_) => "union",
+            },
+            expected,
+            class: self.enclosing_type(),
+        }
+    }
+
     pub fn convert_class(&mut self, idx: pdb::TypeIndex) -> Result<ClassIndex> {
         match self.convert(idx)? {
             TypeIndex::Class(index) => Ok(index),
-            _ => unreachable!("Y u giv me no class?")
+            index => Err(self.unexpected_index(idx, index, "class")),
         }
     }
     pub fn convert_enum(&mut self, idx: pdb::TypeIndex) -> Result<EnumIndex> {
         match self.convert(idx)? {
             TypeIndex::Enum(index) => Ok(index),
-            _ => unreachable!("Y u giv me no enum?")
+            index => Err(self.unexpected_index(idx, index, "enum")),
         }
     }
     pub fn convert_union(&mut self, idx: pdb::TypeIndex) -> Result<UnionIndex> {
         match self.convert(idx)? {
             TypeIndex::Union(index) => Ok(index),
-            _ => unreachable!("Y u giv me no union?")
+            index => Err(self.unexpected_index(idx, index, "union")),
         }
     }
-}
\ No newline at end of file
+}
+
+/// Candidates for the definition of forward references, collected while indexing the types.
+#[derive(Default)]
+struct Definitions {
+    forward_references: Vec<(pdb::TypeIndex, Option<String>, String)>,
+    by_unique_name: HashMap<String, Definition>,
+    by_name: HashMap<String, Definition>,
+}
+
+#[derive(Clone, Copy)]
+struct Definition {
+    idx: pdb::TypeIndex,
+    size: u32,
+    count: u16,
+}
+
+impl Definitions {
+    fn add(&mut self, idx: pdb::TypeIndex, typ: &TypeData, unique_name: Option<&String>) {
+        let (properties, size, count) = match *typ {
+            TypeData::Class(ref class) => (class.properties, class.size as u32, class.count),
+            TypeData::Union(ref u) => (u.properties, u.size, u.count),
+            TypeData::Enumeration(ref e) => (e.properties, 0, e.count),
+            _ => return,
+        };
+        let name = typ.name().unwrap().to_string().into_owned();
+        if properties.forward_reference() {
+            self.forward_references.push((idx, unique_name.cloned(), name));
+            return;
+        }
+        // Some types are defined multiple times with different sizes and fields.
+        // Pick the largest one and the first one of those for determinism.
+        let definition = Definition { idx, size, count };
+        let insert = |map: &mut HashMap<String, Definition>, key: String| {
+            let old = map.entry(key).or_insert(definition);
+            if (size, count) > (old.size, old.count) {
+                *old = definition;
+            }
+        };
+        if let Some(unique_name) = unique_name {
+            insert(&mut self.by_unique_name, unique_name.clone());
+        }
+        // all anonymous types share the same name
+        if !is_anonymous(&name) {
+            insert(&mut self.by_name, name);
+        }
+    }
+
+    /// Returns the definitions of the forward references and the definitions of all named types.
+    fn resolve(self) -> (HashMap<pdb::TypeIndex, pdb::TypeIndex>, HashMap<String, pdb::TypeIndex>) {
+        let Definitions { forward_references, by_unique_name, by_name } = self;
+        let definitions = forward_references.into_iter().filter_map(|(idx, unique_name, name)| {
+            let definition = unique_name.and_then(|unique_name| by_unique_name.get(&unique_name))
+                .or_else(|| by_name.get(&name));
+            match definition {
+                Some(definition) => Some((idx, definition.idx)),
+                None => {
+                    log::debug!("no definition for forward reference {name}");
+                    None
+                }
+            }
+        }).collect();
+        let names = by_name.into_iter().map(|(name, definition)| (name, definition.idx)).collect();
+        (definitions, names)
+    }
+}
+
+/// Names each anonymous type used by a field after the first such field, in the order of
+/// `indexes`, such that the names don't depend on the order in which types are converted.
+fn anonymous_names(finder: &TypeFinder, indexes: &[pdb::TypeIndex], definitions: &HashMap<pdb::TypeIndex, pdb::TypeIndex>) -> HashMap<pdb::TypeIndex, String> {
+    let parse = |idx| finder.find(idx).and_then(|typ| typ.parse()).ok();
+    // the class, union or enum a field type refers to by value or through pointers and arrays
+    let anonymous_type = |mut idx| loop {
+        let typ = parse(idx)?;
+        idx = match typ {
+            TypeData::Pointer(pointer) => pointer.underlying_type,
+            TypeData::Array(array) => array.element_type,
+            TypeData::Modifier(modifier) => modifier.underlying_type,
+            TypeData::Class(_) | TypeData::Union(_) | TypeData::Enumeration(_) => {
+                let name = typ.name()?.to_string();
+                return is_anonymous(&name).then(|| definitions.get(&idx).copied().unwrap_or(idx));
+            }
+            _ => return None,
+        };
+    };
+
+    let mut type_names = HashMap::new();
+    let mut parents = HashMap::new();
+    for &idx in indexes {
+        let (name, fields) = match parse(idx) {
+            Some(TypeData::Class(class)) => (class.name, class.fields),
+            Some(TypeData::Union(u)) => (u.name, Some(u.fields)),
+            _ => continue,
+        };
+        type_names.insert(idx, name.to_string().into_owned());
+        let list = match fields.and_then(parse) {
+            Some(TypeData::FieldList(list)) => list,
+            _ => continue,
+        };
+        for field in list.fields {
+            if let TypeData::Member(member) = field {
+                if let Some(anonymous) = anonymous_type(member.field_type) {
+                    parents.entry(anonymous).or_insert((idx, member.name.to_string().into_owned()));
+                }
+            }
+        }
+    }
+
+    let mut names = HashMap::new();
+    for &idx in parents.keys() {
+        // anonymous parents are named after their own parents
+        let mut fields = Vec::new();
+        let mut current = idx;
+        let parent_name = loop {
+            let (parent, field) = match parents.get(&current) {
+                Some(parent) => parent,
+                None => break None,
+            };
+            fields.push(field);
+            match type_names.get(parent) {
+                Some(name) if !is_anonymous(name) => break Some(name),
+                // anonymous types can use each other through pointers
+                Some(_) if fields.len() < MAX_ANONYMOUS_NESTING => current = *parent,
+                _ => break None,
+            }
+        };
+        if let Some(parent_name) = parent_name {
+            let name = fields.iter().rev().fold(parent_name.clone(), |name, field| format!("{name}__anon_{field}"));
+            names.insert(idx, name);
+        }
+    }
+    names
+}
+
+/// Anonymous types nested deeper are named by their context during conversion.
+const MAX_ANONYMOUS_NESTING: usize = 16;
+
+/// Anonymous types are named `<unnamed-tag>`, `<anonymous-tag>`, `<lambda_...>` etc. in the pdb.
+pub(in crate::ir) fn is_anonymous(name: &str) -> bool {
+    name.starts_with('<')
+}
+
+/// Human readable kind of a pdb type record for error messages.
+fn type_kind(typ: &TypeData) -> &'static str {
+    match typ {
+        TypeData::Primitive(_) => "primitive",
+        TypeData::Class(_) => "class",
+        TypeData::Member(_) => "member",
+        TypeData::MemberFunction(_) => "member function",
+        TypeData::OverloadedMethod(_) => "overloaded method",
+        TypeData::Method(_) => "method",
+        TypeData::StaticMember(_) => "static member",
+        TypeData::Nested(_) => "nested type",
+        TypeData::BaseClass(_) => "base class",
+        TypeData::VirtualBaseClass(_) => "virtual base class",
+        TypeData::VirtualFunctionTablePointer(_) => "vtable pointer",
+        TypeData::Procedure(_) => "procedure",
+        TypeData::Pointer(_) => "pointer",
+        TypeData::Modifier(_) => "modifier",
+        TypeData::Enumeration(_) => "enum",
+        TypeData::Enumerate(_) => "enum variant",
+        TypeData::Array(_) => "array",
+        TypeData::Union(_) => "union",
+        TypeData::Bitfield(_) => "bitfield",
+        TypeData::FieldList(_) => "field list",
+        TypeData::ArgumentList(_) => "argument list",
+        TypeData::MethodList(_) => "method list",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::File;
+
+    use super::*;
+    use crate::dbi::DbiHeader;
+
+    /// Needs a pdb, e.g. `PDBEXTRACT_TEST_PDB=foo.pdb cargo test`.
+    #[test]
+    fn parallel_matches_serial() {
+        let path = match env::var_os("PDBEXTRACT_TEST_PDB") {
+            Some(path) => path,
+            None => return,
+        };
+        let mut pdb = pdb::PDB::open(File::open(path).unwrap()).unwrap();
+        let machine_type = MachineType::from_raw(DbiHeader::read(&mut pdb).unwrap().machine_type);
+        let info = pdb.type_information().unwrap();
+        let table = TypeTable::new(&info, &HashMap::new(), None).unwrap();
+        let mut serial = Arena::new(machine_type);
+        Converter::new(&table, &mut serial).populate().unwrap();
+        // small chunks such that types are converted in several chunks
+        for threads in [1, 4] {
+            let (parallel, _) = table.populate_chunks(machine_type, threads, false, 64).unwrap();
+            assert_eq!(parallel.type_names(), serial.type_names());
+            assert_eq!(format!("{:?}", parallel.classes()), format!("{:?}", serial.classes()));
+            assert_eq!(format!("{:?}", parallel.unions()), format!("{:?}", serial.unions()));
+            assert_eq!(format!("{:?}", parallel.enums()), format!("{:?}", serial.enums()));
+        }
+    }
+}
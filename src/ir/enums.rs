@@ -1,5 +1,5 @@
 use pdb::{EnumerateType, EnumerationType, TypeData};
-use crate::ir::{Name, PrimitiveKind, EnumValue, Properties, Attributes, Converter};
+use crate::ir::{Name, PrimitiveKind, EnumValue, Properties, Attributes, Converter, Origin};
 use crate::{Alignment, Result};
 
 #[derive(Debug)]
@@ -10,39 +10,43 @@ pub struct Enum {
     pub properties: Properties,
     pub count: usize,
     pub alignment: Alignment,
+    /// The type couldn't be converted and only its name and underlying type are known.
+    pub opaque: bool,
+    pub origin: Origin,
 }
 
 impl Enum {
     pub fn from(converter: &mut Converter, e: EnumerationType) -> Result<Enum> {
         let EnumerationType { name, underlying_type, fields, properties, count, .. } = e;
-        let underlying = converter.pdb_type(underlying_type);
-        let underlying = match underlying {
+        let underlying = match converter.pdb_type(underlying_type)? {
             TypeData::Primitive(primitive) => primitive.kind,
-            t => unreachable!("Enum with underlying {:?}", t)
+            t => return Err(converter.unexpected_type(underlying_type, &t, "primitive enum underlying type")),
         };
         let mut variants = Vec::new();
         // pdb contains empty versions of some enums
         if fields != 0 {
-            match converter.pdb_type(fields) {
+            match converter.pdb_type(fields)? {
                 TypeData::FieldList(list) => {
                     for field in list.fields {
                         match field {
                             TypeData::Enumerate(variant) => variants.push(variant.into()),
-                            t => unreachable!("not an Enumerate {:?}", t)
+                            t => return Err(converter.unexpected_type(fields, &t, "enum variant")),
                         }
                     }
                 },
-                t => unreachable!("not a FieldList {:?}", t)
+                t => return Err(converter.unexpected_type(fields, &t, "field list")),
             }
         }
 
         Ok(Enum {
-            name: name.into(),
+            name: converter.type_name(name),
             underlying,
             variants,
             properties: properties.into(),
             count: count as usize,
             alignment: Alignment::None,
+            opaque: false,
+            origin: Origin::Pdb(converter.current_index()),
         })
     }
 }
@@ -0,0 +1,219 @@
+use std::collections::HashMap;
+
+use crate::ir::{Converter, Signature};
+use crate::symbols::{Procedure, Public};
+use crate::{Error, Result};
+
+/// A function of the binary.
+#[derive(Debug)]
+pub struct Function {
+    /// The qualified, undecorated name. For functions without debug information, it's taken
+    /// from the decorated name, which is used as is if it can't be undecorated, e.g. for
+    /// templates and operators.
+    pub name: String,
+    /// The name of the public symbol at the same address, usually decorated, e.g. `?foo@@YAXH@Z`.
+    pub decorated_name: Option<String>,
+    /// Address of the function relative to the image base.
+    pub rva: u32,
+    /// Size of the code in bytes, if known.
+    pub len: Option<u32>,
+    /// `None` for functions without debug information.
+    pub signature: Option<Signature>,
+    /// `false` for functions only visible within their translation unit.
+    pub global: bool,
+}
+
+/// The functions of a pdb, whose signatures refer to types of its `Arena`.
+#[derive(Debug, Default)]
+pub struct Functions {
+    functions: Vec<Function>,
+    /// The functions of each undecorated and decorated name.
+    names: HashMap<String, Vec<usize>>,
+}
+
+impl Functions {
+    pub fn push(&mut self, function: Function) {
+        let index = self.functions.len();
+        self.names.entry(function.name.clone()).or_default().push(index);
+        if let Some(decorated_name) = function.decorated_name.as_ref().filter(|&decorated_name| decorated_name != &function.name) {
+            self.names.entry(decorated_name.clone()).or_default().push(index);
+        }
+        self.functions.push(function);
+    }
+
+    /// Looks up a function by its undecorated or decorated name.
+    ///
+    /// Fails if there is no such function or if the name belongs to several functions at
+    /// different addresses, e.g. overloads.
+    pub fn get(&self, name: &str) -> Result<&Function> {
+        let indices = self.names.get(name).ok_or_else(|| Error::UnknownFunction(name.to_string()))?;
+        let function = &self.functions[indices[0]];
+        // the same function may have debug information in several modules
+        let mut candidates: Vec<_> = indices.iter()
+            .map(|&index| &self.functions[index])
+            .filter(|candidate| candidate.rva != function.rva)
+            .collect();
+        if candidates.is_empty() {
+            return Ok(function);
+        }
+        candidates.insert(0, function);
+        Err(Error::AmbiguousFunction {
+            name: name.to_string(),
+            candidates: candidates.iter()
+                .map(|candidate| candidate.decorated_name.clone().unwrap_or_else(|| candidate.name.clone()))
+                .collect(),
+        })
+    }
+
+    /// All functions with debug information in the order of the modules, followed by the
+    /// public functions without debug information.
+    pub fn iter(&self) -> impl Iterator<Item = &Function> + '_ {
+        self.functions.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.functions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.functions.is_empty()
+    }
+}
+
+impl Converter<'_, '_> {
+    /// Converts the signatures of the procedures and joins them with the public symbols at the
+    /// same address.
+    ///
+    /// In lenient mode, signatures which can't be converted are recorded in the `Report` and
+    /// left out.
+    pub(crate) fn convert_functions(&mut self, procedures: &[Procedure], publics: &[Public]) -> Result<Functions> {
+        let mut functions = Vec::new();
+        let mut by_rva = HashMap::new();
+        for procedure in procedures {
+            let rva = match procedure.rva {
+                Some(rva) => rva,
+                None => continue,
+            };
+            // compiler generated functions may not have a type
+            let signature = if procedure.type_index == 0 {
+                None
+            } else {
+                self.set_field(Some(procedure.name.clone()));
+                let signature = Signature::from(self, procedure.type_index);
+                self.set_field(None);
+                match signature {
+                    Ok(signature) => Some(signature),
+                    Err(e) if self.lenient => {
+                        self.record_failure(procedure.name.clone(), procedure.type_index, e);
+                        None
+                    }
+                    Err(e) => return Err(e),
+                }
+            };
+            by_rva.entry(rva).or_insert(functions.len());
+            functions.push(Function {
+                name: procedure.name.clone(),
+                decorated_name: None,
+                rva,
+                len: Some(procedure.len),
+                signature,
+                global: procedure.global,
+            });
+        }
+        for public in publics {
+            let rva = match public.rva {
+                Some(rva) => rva,
+                None => continue,
+            };
+            match by_rva.get(&rva) {
+                Some(&index) => {
+                    functions[index].decorated_name.get_or_insert_with(|| public.name.clone());
+                }
+                None => {
+                    by_rva.insert(rva, functions.len());
+                    functions.push(Function {
+                        name: undecorate(&public.name).unwrap_or_else(|| public.name.clone()),
+                        decorated_name: Some(public.name.clone()),
+                        rva,
+                        len: None,
+                        signature: None,
+                        global: true,
+                    });
+                }
+            }
+        }
+        let mut res = Functions::default();
+        for function in functions {
+            res.push(function);
+        }
+        Ok(res)
+    }
+}
+
+/// Extracts the qualified name from a decorated MSVC name, e.g. `a::b::f` from
+/// `?f@b@a@@YAXXZ`, including constructors and destructors.
+///
+/// Returns `None` for undecorated names and names using templates, operators or anonymous
+/// namespaces.
+fn undecorate(decorated: &str) -> Option<String> {
+    let mut rest = decorated.strip_prefix('?')?;
+    let special = match rest.strip_prefix('?') {
+        Some(special) => {
+            let (code, special) = special.split_at(special.char_indices().nth(1)?.0);
+            rest = special;
+            Some(code)
+        }
+        None => None,
+    };
+    // the fragments are listed innermost first and can refer back to the first ten of them
+    let mut fragments: Vec<&str> = Vec::new();
+    loop {
+        match rest.chars().next()? {
+            '@' => break,
+            '?' => return None,
+            c @ '0'..='9' => {
+                fragments.push(fragments.get(c as usize - '0' as usize)?);
+                rest = &rest[1..];
+            }
+            _ => {
+                let end = rest.find('@')?;
+                fragments.push(&rest[..end]);
+                rest = &rest[end + 1..];
+            }
+        }
+    }
+    let class = *fragments.first()?;
+    let name = match special {
+        None => None,
+        Some("0") => Some(class.to_string()),
+        Some("1") => Some(format!("~{}", class)),
+        Some(_) => return None,
+    };
+    let mut components: Vec<String> = fragments.iter().rev().map(|fragment| fragment.to_string()).collect();
+    components.extend(name);
+    Some(components.join("::"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::undecorate;
+
+    #[test]
+    fn undecorate_names() {
+        assert_eq!(undecorate("?foo@@YAXH@Z").as_deref(), Some("foo"));
+        assert_eq!(undecorate("?f@b@a@@YAXXZ").as_deref(), Some("a::b::f"));
+        assert_eq!(undecorate("??0Foo@ns@@QEAA@XZ").as_deref(), Some("ns::Foo::Foo"));
+        assert_eq!(undecorate("??1Foo@@UEAA@XZ").as_deref(), Some("Foo::~Foo"));
+        // back-reference to the first fragment
+        assert_eq!(undecorate("?Foo@0@@QEAAXXZ").as_deref(), Some("Foo::Foo"));
+    }
+
+    #[test]
+    fn keep_unsupported_names() {
+        assert_eq!(undecorate("main"), None);
+        assert_eq!(undecorate("??$max@H@std@@YAAEBHAEBH0@Z"), None);
+        assert_eq!(undecorate("??HFoo@@QEAA?AV0@AEBV0@@Z"), None);
+        assert_eq!(undecorate("?f@?A0x1234@@YAXXZ"), None);
+        assert_eq!(undecorate("?"), None);
+    }
+}
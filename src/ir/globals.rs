@@ -0,0 +1,141 @@
+use std::collections::HashMap;
+
+use pdb::DataSymbol;
+
+use crate::ir::{ClassFieldKind, Converter, Name};
+use crate::symbols::Data;
+use crate::Result;
+
+/// A global or static variable.
+#[derive(Debug)]
+pub struct Global {
+    /// The qualified name, e.g. `GWorld` or `Class::member`.
+    pub name: Name,
+    pub kind: ClassFieldKind,
+    /// Section number of the variable, starting at 1.
+    pub segment: u16,
+    /// Offset of the variable within its section.
+    pub offset: u32,
+    /// Address of the variable relative to the image base, `None` if the section is unknown.
+    pub rva: Option<u32>,
+    /// `false` for variables only visible within their translation unit (`S_LDATA32`).
+    pub global: bool,
+}
+
+impl Global {
+    pub fn from(converter: &mut Converter, name: &str, symbol: DataSymbol, rva: Option<u32>) -> Result<Global> {
+        let DataSymbol { global, type_index, offset, segment, .. } = symbol;
+        converter.set_field(Some(name.to_string()));
+        let kind = ClassFieldKind::from(converter, type_index);
+        converter.set_field(None);
+        Ok(Global {
+            name: Name::from(name.to_string()),
+            kind: kind?,
+            segment,
+            offset,
+            rva,
+            global,
+        })
+    }
+}
+
+/// The global and static variables of a pdb, whose types are contained in its `Arena`.
+#[derive(Debug, Default)]
+pub struct Globals {
+    globals: Vec<Global>,
+    /// The first global variable of each name, or the first static one if there is none.
+    names: HashMap<String, usize>,
+}
+
+impl Globals {
+    pub fn push(&mut self, global: Global) {
+        let index = self.globals.len();
+        match self.names.get(&global.name.name) {
+            Some(&existing) if global.global && !self.globals[existing].global => {
+                self.names.insert(global.name.name.clone(), index);
+            }
+            Some(_) => {}
+            None => {
+                self.names.insert(global.name.name.clone(), index);
+            }
+        }
+        self.globals.push(global);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Global> {
+        self.names.get(name).map(|&index| &self.globals[index])
+    }
+
+    /// All variables in the order of the symbol stream, including static ones with the same name.
+    pub fn iter(&self) -> impl Iterator<Item = &Global> + '_ {
+        self.globals.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.globals.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.globals.is_empty()
+    }
+}
+
+impl Converter<'_, '_> {
+    /// Converts the types of the variables.
+    ///
+    /// In lenient mode, variables whose type can't be converted are recorded in the `Report`
+    /// and left out.
+    pub(crate) fn convert_globals(&mut self, data: &[Data]) -> Result<Globals> {
+        let mut globals = Globals::default();
+        for data in data {
+            match Global::from(self, &data.name, data.symbol, data.rva) {
+                Ok(global) => globals.push(global),
+                Err(e) if self.lenient => self.record_failure(data.name.clone(), data.symbol.type_index, e),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(globals)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::PrimitiveKind;
+
+    fn variable(name: &str, offset: u32, global: bool) -> Global {
+        Global {
+            name: Name::from(name.to_string()),
+            kind: ClassFieldKind::Primitive(PrimitiveKind::I32),
+            segment: 1,
+            offset,
+            rva: Some(0x1000 + offset),
+            global,
+        }
+    }
+
+    #[test]
+    fn globals_take_precedence() {
+        let mut globals = Globals::default();
+        globals.push(variable("counter", 0, false));
+        globals.push(variable("GWorld", 8, true));
+        globals.push(variable("counter", 4, true));
+        globals.push(variable("counter", 12, true));
+        globals.push(variable("GWorld", 16, false));
+        assert_eq!(globals.len(), 5);
+        assert_eq!(globals.get("counter").unwrap().offset, 4);
+        assert_eq!(globals.get("GWorld").unwrap().offset, 8);
+        assert!(globals.get("missing").is_none());
+        let offsets: Vec<_> = globals.iter().map(|global| global.offset).collect();
+        assert_eq!(offsets, [0, 8, 4, 12, 16]);
+    }
+
+    #[test]
+    fn first_static_without_globals() {
+        let mut globals = Globals::default();
+        assert!(globals.is_empty());
+        globals.push(variable("counter", 0, false));
+        globals.push(variable("counter", 4, false));
+        assert_eq!(globals.get("counter").unwrap().offset, 0);
+    }
+}
@@ -0,0 +1,329 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::ir::{Arena, Class, ClassMember, ClassField, ClassFieldKind, BitfieldUnderlying, Signature, VirtualMethod, Enum, Union, TypeIndex, ClassIndex, EnumIndex, UnionIndex};
+
+/// Everything which refers to other types of an `Arena`.
+pub trait TypeRefs {
+    /// Calls `f` with every type index this refers to and replaces it with the returned one.
+    fn map_type_refs(&mut self, f: &mut dyn FnMut(TypeIndex) -> TypeIndex);
+}
+
+fn map_class(index: &mut ClassIndex, f: &mut dyn FnMut(TypeIndex) -> TypeIndex) {
+    match f(TypeIndex::Class(*index)) {
+        TypeIndex::Class(new) => *index = new,
+        new => unreachable!("class {:?} mapped to {:?}", index, new),
+    }
+}
+fn map_enum(index: &mut EnumIndex, f: &mut dyn FnMut(TypeIndex) -> TypeIndex) {
+    match f(TypeIndex::Enum(*index)) {
+        TypeIndex::Enum(new) => *index = new,
+        new => unreachable!("enum {:?} mapped to {:?}", index, new),
+    }
+}
+fn map_union(index: &mut UnionIndex, f: &mut dyn FnMut(TypeIndex) -> TypeIndex) {
+    match f(TypeIndex::Union(*index)) {
+        TypeIndex::Union(new) => *index = new,
+        new => unreachable!("union {:?} mapped to {:?}", index, new),
+    }
+}
+
+impl TypeRefs for Class {
+    fn map_type_refs(&mut self, f: &mut dyn FnMut(TypeIndex) -> TypeIndex) {
+        for member in &mut self.members {
+            member.map_type_refs(f);
+        }
+        for nested in &mut self.nested_types {
+            nested.kind.map_type_refs(f);
+        }
+        for member in &mut self.static_members {
+            member.kind.map_type_refs(f);
+        }
+        for method in &mut self.virtual_methods {
+            method.map_type_refs(f);
+        }
+        for derived in &mut self.derived_from {
+            map_class(derived, f);
+        }
+    }
+}
+
+impl TypeRefs for ClassMember {
+    fn map_type_refs(&mut self, f: &mut dyn FnMut(TypeIndex) -> TypeIndex) {
+        match self {
+            ClassMember::Vtable => {}
+            ClassMember::VirtualBasePointer(pointer) => pointer.kind.map_type_refs(f),
+            ClassMember::BaseClass(base) => map_class(&mut base.base_class, f),
+            ClassMember::VirtualBaseClass(base) => {
+                map_class(&mut base.base_class, f);
+                base.base_pointer.map_type_refs(f);
+            }
+            ClassMember::Field(field) => field.map_type_refs(f),
+        }
+    }
+}
+
+impl TypeRefs for ClassField {
+    fn map_type_refs(&mut self, f: &mut dyn FnMut(TypeIndex) -> TypeIndex) {
+        self.kind.map_type_refs(f);
+    }
+}
+
+impl TypeRefs for ClassFieldKind {
+    fn map_type_refs(&mut self, f: &mut dyn FnMut(TypeIndex) -> TypeIndex) {
+        match self {
+            ClassFieldKind::Enum(e) => map_enum(e, f),
+            ClassFieldKind::Class(c) => map_class(c, f),
+            ClassFieldKind::Union(u) => map_union(u, f),
+            ClassFieldKind::Pointer(p) => p.underlying.map_type_refs(f),
+            ClassFieldKind::Array(a) => a.element_type.map_type_refs(f),
+            ClassFieldKind::Modifier(m) => m.underlying.map_type_refs(f),
+            ClassFieldKind::Bitfield(b) => for field in &mut b.fields {
+                if let BitfieldUnderlying::Enum(e) = &mut field.underlying {
+                    map_enum(e, f);
+                }
+            }
+            ClassFieldKind::Procedure(signature)
+            | ClassFieldKind::MemberFunction(signature)
+            | ClassFieldKind::Method(signature) => signature.map_type_refs(f),
+            ClassFieldKind::Primitive(_) => {}
+        }
+    }
+}
+
+impl TypeRefs for VirtualMethod {
+    fn map_type_refs(&mut self, f: &mut dyn FnMut(TypeIndex) -> TypeIndex) {
+        self.signature.map_type_refs(f);
+    }
+}
+
+impl TypeRefs for Signature {
+    fn map_type_refs(&mut self, f: &mut dyn FnMut(TypeIndex) -> TypeIndex) {
+        self.return_type.map_type_refs(f);
+        for argument in &mut self.arguments {
+            argument.map_type_refs(f);
+        }
+        if let Some(this_type) = &mut self.this_type {
+            this_type.map_type_refs(f);
+        }
+    }
+}
+
+impl TypeRefs for Enum {
+    fn map_type_refs(&mut self, _: &mut dyn FnMut(TypeIndex) -> TypeIndex) {}
+}
+
+impl TypeRefs for Union {
+    fn map_type_refs(&mut self, f: &mut dyn FnMut(TypeIndex) -> TypeIndex) {
+        for field in &mut self.fields {
+            field.map_type_refs(f);
+        }
+        for nested in &mut self.nested_types {
+            nested.kind.map_type_refs(f);
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Action {
+    Append,
+    Replace(TypeIndex),
+    Existing(TypeIndex),
+}
+
+impl Arena {
+    fn map_type_refs(&mut self, index: TypeIndex, f: &mut dyn FnMut(TypeIndex) -> TypeIndex) {
+        match index {
+            TypeIndex::Class(c) => self[c].map_type_refs(f),
+            TypeIndex::Enum(e) => self[e].map_type_refs(f),
+            TypeIndex::Union(u) => self[u].map_type_refs(f),
+        }
+    }
+
+    fn type_indices(&self) -> impl Iterator<Item = TypeIndex> {
+        let classes = (0..self.classes().len()).map(|i| TypeIndex::Class(ClassIndex(i)));
+        let enums = (0..self.enums().len()).map(|i| TypeIndex::Enum(EnumIndex(i)));
+        let unions = (0..self.unions().len()).map(|i| TypeIndex::Union(UnionIndex(i)));
+        classes.chain(enums).chain(unions)
+    }
+
+    /// Adds all types of an arena converted from the same pdb.
+    ///
+    /// Types which are already contained are skipped, unless they are only contained as opaque
+    /// type. Synthesized types without pdb index are taken together with the types using them.
+    pub fn merge(&mut self, mut other: Arena) {
+        let mut actions = HashMap::new();
+        let mut taken = Vec::new();
+        for index in other.type_indices() {
+            let idx = match other.origin(index).pdb_index() {
+                Some(idx) => idx,
+                None => continue,
+            };
+            let action = match self.index_map().get(&idx) {
+                Some(&existing) if self.is_opaque(existing) && !other.is_opaque(index) => Action::Replace(existing),
+                Some(&existing) => Action::Existing(existing),
+                None => Action::Append,
+            };
+            if !matches!(action, Action::Existing(_)) {
+                taken.push(index);
+            }
+            actions.insert(index, action);
+        }
+        // synthesized types are only needed if a taken type uses them
+        let without_origin: HashSet<_> = other.type_indices()
+            .filter(|&index| other.origin(index).pdb_index().is_none())
+            .collect();
+        let mut synthesized = HashSet::new();
+        while let Some(index) = taken.pop() {
+            other.map_type_refs(index, &mut |referenced| {
+                if without_origin.contains(&referenced) && synthesized.insert(referenced) {
+                    taken.push(referenced);
+                }
+                referenced
+            });
+        }
+        for &index in &synthesized {
+            actions.insert(index, Action::Append);
+        }
+
+        let (mut classes, mut enums, mut unions) = (self.classes().len(), self.enums().len(), self.unions().len());
+        let mut remap = HashMap::new();
+        for index in other.type_indices() {
+            let new = match actions.get(&index) {
+                Some(Action::Append) => match index {
+                    TypeIndex::Class(_) => { classes += 1; TypeIndex::Class(ClassIndex(classes - 1)) }
+                    TypeIndex::Enum(_) => { enums += 1; TypeIndex::Enum(EnumIndex(enums - 1)) }
+                    TypeIndex::Union(_) => { unions += 1; TypeIndex::Union(UnionIndex(unions - 1)) }
+                },
+                Some(Action::Replace(existing) | Action::Existing(existing)) => *existing,
+                None => continue,
+            };
+            remap.insert(index, new);
+        }
+        let mut remap_ref = |index| remap[&index];
+
+        let other_classes = std::mem::take(other.classes_mut());
+        for (i, mut class) in other_classes.into_iter().enumerate() {
+            let index = TypeIndex::Class(ClassIndex(i));
+            match actions.get(&index) {
+                Some(Action::Append) => {
+                    class.map_type_refs(&mut remap_ref);
+                    match class.origin.pdb_index() {
+                        Some(idx) => self.insert_class(class, idx),
+                        None => self.insert_custom_class(class),
+                    };
+                }
+                Some(&Action::Replace(TypeIndex::Class(existing))) => {
+                    class.map_type_refs(&mut remap_ref);
+                    self.replace_class(existing, class);
+                }
+                _ => {}
+            }
+        }
+        let other_enums = std::mem::take(other.enums_mut());
+        for (i, mut e) in other_enums.into_iter().enumerate() {
+            let index = TypeIndex::Enum(EnumIndex(i));
+            match actions.get(&index) {
+                Some(Action::Append) => {
+                    e.map_type_refs(&mut remap_ref);
+                    match e.origin.pdb_index() {
+                        Some(idx) => self.insert_enum(e, idx),
+                        None => self.insert_custom_enum(e),
+                    };
+                }
+                Some(&Action::Replace(TypeIndex::Enum(existing))) => {
+                    e.map_type_refs(&mut remap_ref);
+                    self.replace_enum(existing, e);
+                }
+                _ => {}
+            }
+        }
+        let other_unions = std::mem::take(other.unions_mut());
+        for (i, mut u) in other_unions.into_iter().enumerate() {
+            let index = TypeIndex::Union(UnionIndex(i));
+            match actions.get(&index) {
+                Some(Action::Append) => {
+                    u.map_type_refs(&mut remap_ref);
+                    match u.origin.pdb_index() {
+                        Some(idx) => self.insert_union(u, idx),
+                        None => self.insert_custom_union(u),
+                    };
+                }
+                Some(&Action::Replace(TypeIndex::Union(existing))) => {
+                    u.map_type_refs(&mut remap_ref);
+                    self.replace_union(existing, u);
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Alignment;
+    use crate::MachineType;
+    use crate::ir::{Attributes, ClassKind, Name, Origin, Properties};
+    use super::*;
+
+    fn class(name: &str, size: usize, fields: &[ClassIndex], origin: Origin, opaque: bool) -> Class {
+        Class {
+            name: Name::from(name.to_string()),
+            kind: ClassKind::Struct,
+            members: fields.iter().map(|&field| ClassMember::Field(ClassField {
+                attributes: Attributes::default(),
+                name: Name::from("field".to_string()),
+                offset: 0,
+                kind: ClassFieldKind::Class(field),
+                alias: None,
+                max_size: usize::MAX,
+            })).collect(),
+            properties: Properties::default(),
+            nested_types: Vec::new(),
+            static_members: Vec::new(),
+            virtual_methods: Vec::new(),
+            vtable_shape: None,
+            derived_from: Vec::new(),
+            size,
+            alignment: Alignment::None,
+            opaque,
+            origin,
+        }
+    }
+
+    fn field_type(class: &Class) -> ClassIndex {
+        match &class.members[0] {
+            ClassMember::Field(ClassField { kind: ClassFieldKind::Class(index), .. }) => *index,
+            member => panic!("unexpected member {:?}", member),
+        }
+    }
+
+    #[test]
+    fn merge() {
+        let mut arena = Arena::new(MachineType::Amd64);
+        arena.insert_class(class("Foo", 8, &[], Origin::Pdb(0x1000), false), 0x1000);
+        arena.insert_class(class("Bar", 4, &[], Origin::Pdb(0x1001), true), 0x1001);
+
+        let mut other = Arena::new(MachineType::Amd64);
+        // only used by a type which is already contained
+        let unused = other.insert_custom_class(class("Foo_Struct0", 4, &[], Origin::Synthesized { parent: 0x1000 }, false));
+        other.insert_class(class("Foo", 16, &[unused], Origin::Pdb(0x1000), false), 0x1000);
+        let used = other.insert_custom_class(class("Baz_Struct0", 4, &[], Origin::Synthesized { parent: 0x1002 }, false));
+        let baz = other.insert_class(class("Baz", 4, &[used], Origin::Pdb(0x1002), false), 0x1002);
+        other.insert_class(class("Bar", 4, &[baz], Origin::Pdb(0x1001), false), 0x1001);
+
+        arena.merge(other);
+        arena.choose_type_names();
+        let names: Vec<_> = arena.classes().iter().map(|class| class.name.name.as_str()).collect();
+        assert_eq!(names, ["Foo", "Bar", "Baz_Struct0", "Baz"]);
+        // existing types are kept
+        assert_eq!(arena[ClassIndex(0)].size, 8);
+        assert!(arena[ClassIndex(0)].members.is_empty());
+        // opaque types are replaced in place, and references are remapped
+        let bar = &arena[ClassIndex(1)];
+        assert!(!bar.opaque);
+        assert_eq!(field_type(bar), ClassIndex(3));
+        assert_eq!(field_type(&arena[ClassIndex(3)]), ClassIndex(2));
+        assert_eq!(arena.index_map()[&0x1002], TypeIndex::Class(ClassIndex(3)));
+        assert_eq!(arena.type_names().len(), 4);
+    }
+}
@@ -6,6 +6,14 @@ mod name;
 mod size;
 mod convert;
 mod write;
+mod report;
+mod merge;
+mod progress;
+mod scope;
+mod globals;
+mod functions;
+mod alias;
+mod constants;
 
 use pdb;
 
@@ -19,3 +27,11 @@ pub use self::name::*;
 pub use self::size::*;
 pub use self::convert::*;
 pub use self::write::*;
+pub use self::report::*;
+pub use self::merge::*;
+pub use self::progress::*;
+pub use self::scope::*;
+pub use self::globals::*;
+pub use self::functions::*;
+pub use self::alias::*;
+pub use self::constants::*;
@@ -55,11 +55,7 @@ impl From<String> for Name {
                 generics.push(name);
             }
         }
-        static RE: Lazy<Regex> = Lazy::new(|| Regex::new("[^a-zA-z0-9]+").unwrap());
-        let ident = name.to_string()
-            .replace("*", "star")
-            .replace("&", "amp");
-        let ident = RE.replace_all(ident.to_string().as_ref(), "_").into_owned();
+        let ident = ident(&name);
         Name {
             name,
             ident,
@@ -94,6 +90,35 @@ impl Hash for Name {
     }
 }
 
+/// Converts a C++ name into a rust identifier.
+pub fn ident(name: &str) -> String {
+    static RE: Lazy<Regex> = Lazy::new(|| Regex::new("[^a-zA-z0-9]+").unwrap());
+    let ident = name.to_string()
+        .replace("*", "star")
+        .replace("&", "amp");
+    RE.replace_all(ident.to_string().as_ref(), "_").into_owned()
+}
+
+/// Splits a qualified name into the enclosing scope and the last component, ignoring `::`
+/// inside of template arguments, e.g. `a::b<c::d>::e` into `Some("a::b<c::d>")` and `"e"`.
+pub fn split_scope(name: &str) -> (Option<&str>, &str) {
+    let mut level = 0i32;
+    let mut split = None;
+    let bytes = name.as_bytes();
+    for (i, &c) in bytes.iter().enumerate() {
+        match c {
+            b'<' | b'(' => level += 1,
+            b'>' | b')' => level -= 1,
+            b':' if level == 0 && bytes.get(i + 1) == Some(&b':') => split = Some(i),
+            _ => {}
+        }
+    }
+    match split {
+        Some(i) => (Some(&name[..i]), &name[i + 2..]),
+        None => (None, name),
+    }
+}
+
 /// Returns a tuple of the first index of start and everything between the start and end characters
 /// including inner appearances of start and end.
 fn get_between(s: &str, start: char, end: char) -> Option<(usize, &str)> {
@@ -107,7 +132,6 @@ fn get_between(s: &str, start: char, end: char) -> Option<(usize, &str)> {
             _ => {},
         }
         if level == 0 {
-            assert!(search_in.len() == i + 1 || search_in.chars().nth(i+1) == Some(':'));
             return Some((start_index, &search_in[.. i]));
         }
     }
@@ -0,0 +1,33 @@
+use std::fmt;
+
+/// Phases of the conversion of a pdb, in the order they start.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    /// Reading all type records of the TPI stream, `total` is the number of records.
+    Indexing,
+    /// Reading the procedures of the modules, `total` is the number of modules.
+    Symbols,
+    /// Converting the types into the arena, `total` is the number of named types, or the number
+    /// of requested names for `parse_types`.
+    ///
+    /// Bitfields, inline unions and inline structs are transformed right when their class is
+    /// converted, so there's no separate phase for them.
+    Converting,
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Phase::Indexing => "indexing",
+            Phase::Symbols => "symbols",
+            Phase::Converting => "converting",
+        })
+    }
+}
+
+/// Receives progress updates while a pdb is parsed.
+///
+/// During parallel conversion, `update` is called from multiple threads at once.
+pub trait Progress: Send + Sync {
+    fn update(&self, phase: Phase, done: usize, total: usize);
+}
@@ -0,0 +1,69 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use pdb;
+
+use crate::Error;
+
+/// A type which couldn't be converted and was replaced by an opaque type.
+#[derive(Debug)]
+pub struct Failure {
+    pub name: String,
+    pub index: pdb::TypeIndex,
+    pub reason: Error,
+    /// Names of the types whose conversion led to this type, outermost first.
+    pub stack: Vec<String>,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} (pdb type {:#x}): {}", self.name, self.index, self.reason)?;
+        if !self.stack.is_empty() {
+            write!(f, " [in {}]", self.stack.join(" -> "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Collects all failures of a lenient conversion.
+#[derive(Debug, Default)]
+pub struct Report {
+    pub failures: Vec<Failure>,
+}
+
+impl Report {
+    pub fn is_empty(&self) -> bool {
+        self.failures.is_empty()
+    }
+
+    /// Adds the failures of another report, skipping those which already failed.
+    ///
+    /// Failures are the same if they have the same name and pdb index, such that e.g. several
+    /// globals of the same type are all kept.
+    pub fn merge(&mut self, other: Report) {
+        let failed: HashSet<_> = self.failures.iter().map(|f| (f.index, f.name.clone())).collect();
+        self.failures.extend(other.failures.into_iter().filter(|f| !failed.contains(&(f.index, f.name.clone()))));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn failure(name: &str, index: pdb::TypeIndex) -> Failure {
+        Failure {
+            name: name.to_string(),
+            index,
+            reason: Error::Unimplemented("test".to_string()),
+            stack: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn merge() {
+        let mut report = Report { failures: vec![failure("Foo", 0x1000)] };
+        report.merge(Report { failures: vec![failure("Foo", 0x1000), failure("g_foo", 0x1000), failure("Bar", 0x1001)] });
+        let failures: Vec<_> = report.failures.iter().map(|f| (f.name.as_str(), f.index)).collect();
+        assert_eq!(failures, [("Foo", 0x1000), ("g_foo", 0x1000), ("Bar", 0x1001)]);
+    }
+}
@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+
+use crate::ir::{Arena, ClassFieldKind, NestedType, TypeIndex, split_scope, is_anonymous};
+
+/// A namespace or type together with everything declared inside of it.
+#[derive(Debug, Default)]
+pub struct Scope {
+    /// The name within the enclosing scope, empty for the global namespace.
+    pub name: String,
+    /// The type, `None` for namespaces.
+    pub index: Option<TypeIndex>,
+    /// Ordered by name.
+    pub children: Vec<Scope>,
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+enum ScopeKey {
+    /// The qualified name of a namespace.
+    Namespace(String),
+    Type(TypeIndex),
+}
+
+impl Arena {
+    /// Builds the tree of namespaces, types and the types nested inside of them.
+    ///
+    /// Each name appears once, referring to the type chosen by the `DuplicatePolicy`.
+    /// Nested types are taken from the nested type declarations of their parent; all other
+    /// scopes are derived from the qualified names.
+    pub fn scopes(&self) -> Scope {
+        let mut names: Vec<_> = self.type_names().iter()
+            .map(|(name, &index)| (name.as_str(), self.resolve_type_index(index)))
+            .collect();
+        names.sort();
+
+        let mut parents = HashMap::new();
+        for &(name, index) in &names {
+            let nested_types = match index {
+                TypeIndex::Class(c) => &self[c].nested_types,
+                TypeIndex::Union(u) => &self[u].nested_types,
+                TypeIndex::Enum(_) => continue,
+            };
+            for nested in nested_types {
+                let child = match self.nested_index(nested) {
+                    Some(child) => child,
+                    None => continue,
+                };
+                // typedefs inside the type are declarations of other types, anonymous types
+                // are named after their parent instead
+                if is_anonymous(&nested.name.name) || self.name_of(child).name == format!("{}::{}", name, nested.name.name) {
+                    parents.insert(child, index);
+                }
+            }
+        }
+
+        let mut children: HashMap<ScopeKey, Vec<(String, ScopeKey)>> = HashMap::new();
+        for &(name, index) in &names {
+            let (scope, last) = split_scope(name);
+            let parent = match (parents.get(&index), scope) {
+                (Some(&parent), _) => ScopeKey::Type(parent),
+                (None, Some(scope)) => match self.type_names().get(scope) {
+                    Some(&parent) => ScopeKey::Type(self.resolve_type_index(parent)),
+                    None => self.add_namespace(&mut children, scope),
+                },
+                (None, None) => ScopeKey::Namespace(String::new()),
+            };
+            children.entry(parent).or_default().push((last.to_string(), ScopeKey::Type(index)));
+        }
+        build_scope(String::new(), ScopeKey::Namespace(String::new()), &mut children)
+    }
+
+    /// Adds the namespace and all its enclosing namespaces to the tree if they are missing.
+    fn add_namespace(&self, children: &mut HashMap<ScopeKey, Vec<(String, ScopeKey)>>, namespace: &str) -> ScopeKey {
+        let key = ScopeKey::Namespace(namespace.to_string());
+        if children.contains_key(&key) {
+            return key;
+        }
+        children.insert(key.clone(), Vec::new());
+        let (scope, last) = split_scope(namespace);
+        let parent = match scope {
+            Some(scope) => self.add_namespace(children, scope),
+            None => ScopeKey::Namespace(String::new()),
+        };
+        children.entry(parent).or_default().push((last.to_string(), key.clone()));
+        key
+    }
+
+    fn nested_index(&self, nested: &NestedType) -> Option<TypeIndex> {
+        let index = match nested.kind {
+            ClassFieldKind::Class(c) => TypeIndex::Class(c),
+            ClassFieldKind::Enum(e) => TypeIndex::Enum(e),
+            ClassFieldKind::Union(u) => TypeIndex::Union(u),
+            _ => return None,
+        };
+        Some(self.resolve_type_index(index))
+    }
+}
+
+fn build_scope(name: String, key: ScopeKey, children: &mut HashMap<ScopeKey, Vec<(String, ScopeKey)>>) -> Scope {
+    let mut scope_children: Vec<_> = children.remove(&key).unwrap_or_default().into_iter()
+        .map(|(name, child)| build_scope(name, child, children))
+        .collect();
+    scope_children.sort_by(|a, b| a.name.cmp(&b.name));
+    Scope {
+        name,
+        index: match key {
+            ScopeKey::Type(index) => Some(index),
+            ScopeKey::Namespace(_) => None,
+        },
+        children: scope_children,
+    }
+}
+
+impl Scope {
+    /// Calls `f` with every scope and its qualified name, parents before their children.
+    pub fn walk<F: FnMut(&str, &Scope)>(&self, f: &mut F) {
+        self.walk_inner("", f);
+    }
+
+    fn walk_inner<F: FnMut(&str, &Scope)>(&self, parent: &str, f: &mut F) {
+        let qualified = match parent {
+            "" => self.name.clone(),
+            parent => format!("{}::{}", parent, self.name),
+        };
+        f(&qualified, self);
+        for child in &self.children {
+            child.walk_inner(&qualified, f);
+        }
+    }
+}
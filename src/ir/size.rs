@@ -13,8 +13,8 @@ impl Size for Class {
 impl Size for ClassMember {
     fn size(&self, arena: &Arena) -> usize {
         match self {
-            // TODO: get actual pdb pointer size
-            ClassMember::Vtable => 4,
+            ClassMember::Vtable => arena.pointer_size(),
+            ClassMember::VirtualBasePointer(_) => arena.pointer_size(),
             ClassMember::BaseClass(class) => class.size(arena),
             ClassMember::VirtualBaseClass(class) => class.size(arena),
             ClassMember::Field(field) => field.size(arena),
@@ -24,13 +24,15 @@ impl Size for ClassMember {
 
 impl Size for BaseClass {
     fn size(&self, arena: &Arena) -> usize {
-        arena[self.base_class].size
+        arena[self.base_class].non_virtual_size(arena)
     }
 }
 
 impl Size for VirtualBaseClass {
+    /// The space taken within the derived class, which doesn't include the virtual bases of the
+    /// base class.
     fn size(&self, arena: &Arena) -> usize {
-        arena[self.base_class].size
+        arena[self.base_class].non_virtual_size(arena)
     }
 }
 
@@ -44,18 +46,17 @@ impl Size for ClassFieldKind {
     fn size(&self, arena: &Arena) -> usize {
         match *self {
             ClassFieldKind::Primitive(primitive) => primitive.size(arena),
-            ClassFieldKind::Enum(e) => arena.get_largest_enum(e).size(arena),
-            // TODO: get actual pdb pointer size
-            ClassFieldKind::Pointer(_) => 4,
-            ClassFieldKind::Class(c) => arena.get_largest_class(c).size(arena),
+            ClassFieldKind::Enum(e) => arena.resolve_enum(e).size(arena),
+            ClassFieldKind::Pointer(ref p) => p.size(arena),
+            ClassFieldKind::Class(c) => arena.resolve_class(c).size(arena),
             ClassFieldKind::Bitfield(ref b) => b.size(arena),
-            ClassFieldKind::Union(u) => arena.get_largest_union(u).size(arena),
+            ClassFieldKind::Union(u) => arena.resolve_union(u).size(arena),
             ClassFieldKind::Array(ref a) => a.size(arena),
             ClassFieldKind::Modifier(ref m) => m.size(arena),
             // ignore because those aren't actual fields
-            ClassFieldKind::Procedure => 0,
-            ClassFieldKind::MemberFunction => 0,
-            ClassFieldKind::Method => 0,
+            ClassFieldKind::Procedure(_) => 0,
+            ClassFieldKind::MemberFunction(_) => 0,
+            ClassFieldKind::Method(_) => 0,
         }
     }
 }
@@ -82,16 +83,20 @@ impl Size for PrimitiveKind {
             PrimitiveKind::U128 => 16,
             PrimitiveKind::F16 => 2,
             PrimitiveKind::F32 => 4,
+            PrimitiveKind::F32PP => 4,
             PrimitiveKind::F48 => 6,
             PrimitiveKind::F64 => 8,
             PrimitiveKind::F80 => 10,
             PrimitiveKind::F128 => 16,
+            PrimitiveKind::Complex32 => 8,
+            PrimitiveKind::Complex64 => 16,
+            PrimitiveKind::Complex80 => 20,
+            PrimitiveKind::Complex128 => 32,
             PrimitiveKind::Bool8 => 1,
             PrimitiveKind::Bool16 => 2,
             PrimitiveKind::Bool32 => 4,
             PrimitiveKind::Bool64 => 8,
             PrimitiveKind::HRESULT => 4,
-            t => unimplemented!("size: primitive: {:?}", t)
         }
     }
 }
@@ -104,7 +109,7 @@ impl Size for Enum {
 
 impl Size for Bitfield {
     fn size(&self, arena: &Arena) -> usize {
-        self.fields.iter().map(|f| f.size(arena)).max().unwrap()
+        self.fields.iter().map(|f| f.size(arena)).max().unwrap_or(0)
     }
 }
 
@@ -129,8 +134,54 @@ impl Size for Array {
     }
 }
 
+impl Size for Pointer {
+    fn size(&self, arena: &Arena) -> usize {
+        // the size in the pointer record is 0 for some old records
+        if self.size != 0 { self.size } else { arena.pointer_size() }
+    }
+}
+
 impl Size for Modifier {
     fn size(&self, arena: &Arena) -> usize {
         self.underlying.size(arena)
     }
 }
+
+/// The natural alignment of a type, which is only needed where the pdb doesn't contain offsets.
+pub trait Align {
+    fn align(&self, arena: &Arena) -> usize;
+}
+
+impl Align for Class {
+    fn align(&self, arena: &Arena) -> usize {
+        if self.properties.packed {
+            return 1;
+        }
+        self.members.iter().map(|member| member.align(arena)).max().unwrap_or(1)
+    }
+}
+
+impl Align for ClassMember {
+    fn align(&self, arena: &Arena) -> usize {
+        match self {
+            ClassMember::Vtable | ClassMember::VirtualBasePointer(_) => arena.pointer_size(),
+            ClassMember::BaseClass(base) => arena[base.base_class].align(arena),
+            ClassMember::VirtualBaseClass(base) => arena[base.base_class].align(arena),
+            ClassMember::Field(field) => field.kind.align(arena),
+        }
+    }
+}
+
+impl Align for ClassFieldKind {
+    fn align(&self, arena: &Arena) -> usize {
+        match self {
+            ClassFieldKind::Class(c) => arena.resolve_class(*c).align(arena),
+            ClassFieldKind::Union(u) => arena.resolve_union(*u).fields.iter()
+                .map(|field| field.kind.align(arena))
+                .max().unwrap_or(1),
+            ClassFieldKind::Array(a) => a.element_type.align(arena),
+            ClassFieldKind::Modifier(m) => m.underlying.align(arena),
+            kind => kind.size(arena).max(1),
+        }
+    }
+}
@@ -1,56 +1,63 @@
 use std::collections::VecDeque;
 use pdb::{UnionType, TypeData, ClassKind};
-use crate::ir::{Name, ClassField, Properties, Converter, Size, Class, ClassMember, ClassFieldKind};
-use crate::{Alignment, Arena, Result};
+use crate::ir::{Name, ClassField, max_size, Properties, Converter, Size, Class, ClassMember, ClassFieldKind, Origin, NestedType};
+use crate::{Alignment, Arena, Error, Result};
 
 #[derive(Debug)]
 pub struct Union {
     pub name: Name,
     pub fields: Vec<ClassField>,
     pub properties: Properties,
+    /// Types declared inside of the union, including typedefs.
+    pub nested_types: Vec<NestedType>,
     pub size: usize,
     pub count: u16,
     pub alignment: Alignment,
+    /// The type couldn't be converted and only its name and size are known.
+    pub opaque: bool,
+    pub origin: Origin,
 }
 
 impl Union {
     pub fn from(converter: &mut Converter, u: UnionType) -> Result<Union> {
         let UnionType { name, fields, properties, size, count } = u;
         let mut members = VecDeque::new();
+        let mut nested = Vec::new();
         // pdb contains empty versions of some unions
         if fields != 0 {
-            match converter.pdb_type(fields) {
+            match converter.pdb_type(fields)? {
                 TypeData::FieldList(list) => {
                     let mut peekable = list.fields.into_iter().peekable();
-                    let mut last_offset = 0;
                     while let Some(field) = peekable.next() {
-                        let max_size = peekable.peek().map(|t| match t {
-                            TypeData::Member(field) => field.offset as usize - last_offset,
-                            _ => usize::MAX,
-                        }).unwrap_or(size as usize - last_offset);
                         match field {
                             TypeData::Member(member) => {
-                                last_offset = member.offset as usize;
+                                let max_size = max_size(peekable.peek(), member.offset as usize, size as usize);
                                 members.push_back(ClassField::from(converter, member, max_size)?);
                             }
-                            TypeData::Nested(_) => {},
+                            TypeData::Nested(nested_type) => nested.push(nested_type),
                             TypeData::Method(_) => {},
-                            t => unreachable!("not a member {:?}", t)
+                            t => return Err(converter.unexpected_type(fields, &t, "union member")),
                         }
                     }
                 }
-                t => unreachable!("Not a FieldList {:?}", t)
+                t => return Err(converter.unexpected_type(fields, &t, "field list")),
             }
         }
-        let name = name.into();
-        let members = Self::transform_inline_structs(&mut converter.arena, &name, members);
+        // after the members, such that anonymous types are named after the field using them
+        let nested_types = NestedType::from_all(converter, nested);
+        let name = converter.type_name(name);
+        let origin = Origin::Pdb(converter.current_index());
+        let members = Self::transform_inline_structs(&mut converter.arena, &name, origin, members)?;
         Ok(Union {
-            name: name.into(),
+            name,
             fields: members,
             properties: properties.into(),
+            nested_types,
             size: size as usize,
             count,
             alignment: Alignment::None,
+            opaque: false,
+            origin,
         })
     }
 
@@ -75,12 +82,14 @@ impl Union {
     //
     // To generate rust types, we need to detect these inner structs and create new types for them.
     // For simplification, for each substruct (even if its just a single field), we create a new struct.
-    fn transform_inline_structs(arena: &mut Arena, name: &Name, mut fields: VecDeque<ClassField>) -> Vec<ClassField> {
+    fn transform_inline_structs(arena: &mut Arena, name: &Name, origin: Origin, mut fields: VecDeque<ClassField>) -> Result<Vec<ClassField>> {
         let mut res = Vec::with_capacity(fields.len());
         let mut struct_number = 0;
 
         while let Some(field) = fields.pop_front() {
-            assert_eq!(0, field.offset);
+            if field.offset != 0 {
+                return Err(Error::UnexpectedLayout(format!("member {} of union {} at {:#x} instead of 0", field.name.name, name.name, field.offset)));
+            }
             if fields.front().is_none() || fields.front().unwrap().offset == 0 {
                 res.push(field);
                 continue;
@@ -98,19 +107,27 @@ impl Union {
                 kind: ClassKind::Struct,
                 members: inner_members,
                 properties: Default::default(),
+                nested_types: Vec::new(),
+                static_members: Vec::new(),
+                virtual_methods: Vec::new(),
+                vtable_shape: None,
+                derived_from: Vec::new(),
                 size,
                 alignment: Alignment::None,
+                opaque: false,
+                origin: origin.synthesized(),
             });
             res.push(ClassField {
                 attributes: Default::default(),
                 name: format!("struct{}", struct_number).into(),
                 offset: 0,
                 kind: ClassFieldKind::Class(inner_struct_index),
+                alias: None,
                 max_size: size,
             });
             struct_number += 1;
         }
 
-        res
+        Ok(res)
     }
 }
\ No newline at end of file
@@ -1,10 +1,11 @@
 use std::io::Write;
-use std::collections::VecDeque;
+use std::collections::{HashSet, VecDeque};
 use std::mem;
+use std::ops::Range;
 use std::borrow::Cow;
 
 use crate::ir::*;
-use crate::{Alignment, Result};
+use crate::{Alignment, Error, MachineType, Result};
 
 pub struct Writer<'a, W: Write> {
     w: W,
@@ -18,6 +19,16 @@ pub struct Writer<'a, W: Write> {
     union_number: usize,
     current_fields: Vec<String>,
     is_pointer_field: bool,
+    /// Offset of the base class currently being written within the written class,
+    /// `None` within virtual base classes.
+    current_base_offset: Option<usize>,
+    /// Offsets of the vtable pointers of the written class which have a vtable struct.
+    current_vtables: Vec<usize>,
+    /// End of the last written member of the class, used to pad up to its virtual bases.
+    current_end: usize,
+    /// Typedefs used by the written fields, which are written by `write_rest`.
+    alias_todo: Vec<String>,
+    written_aliases: HashSet<String>,
 }
 
 impl<'a, W: Write> Writer<'a, W> {
@@ -35,6 +46,11 @@ impl<'a, W: Write> Writer<'a, W> {
             union_number: 0,
             current_fields: Vec::new(),
             is_pointer_field: false,
+            current_base_offset: Some(0),
+            current_vtables: Vec::new(),
+            current_end: 0,
+            alias_todo: Vec::new(),
+            written_aliases: HashSet::new(),
         })
     }
 
@@ -56,7 +72,7 @@ impl<'a, W: Write> Writer<'a, W> {
     }
 
     fn add_todo(&mut self, index: TypeIndex) {
-        let index = self.arena.get_largest_type_index(index);
+        let index = self.arena.resolve_type_index(index);
         if self.written.contains(&index) {
             return;
         }
@@ -71,7 +87,7 @@ impl<'a, W: Write> Writer<'a, W> {
     }
 
     fn add_stub(&mut self, index: TypeIndex) {
-        let index = self.arena.get_largest_type_index(index);
+        let index = self.arena.resolve_type_index(index);
         if self.written.contains(&index) {
             return;
         }
@@ -86,7 +102,7 @@ impl<'a, W: Write> Writer<'a, W> {
     }
 
     fn add_generics<F: Fn(&mut Self, TypeIndex)>(&mut self, index: TypeIndex, add_fn: F) {
-        let index = self.arena.get_largest_type_index(index);
+        let index = self.arena.resolve_type_index(index);
         let name = match index {
             TypeIndex::Class(c) => &self.arena[c].name,
             TypeIndex::Union(u) => &self.arena[u].name,
@@ -101,7 +117,7 @@ impl<'a, W: Write> Writer<'a, W> {
     }
 
     pub fn write_type(&mut self, index: TypeIndex) -> Result<()> {
-        let index = self.arena.get_largest_type_index(index);
+        let index = self.arena.resolve_type_index(index);
         self.add_written(index);
         match index {
             TypeIndex::Class(c) => self.write_class(&self.arena[c]),
@@ -145,11 +161,111 @@ impl<'a, W: Write> Writer<'a, W> {
 
     pub fn write_rest(&mut self) -> Result<()> {
         self.write_todos()?;
+        // the aliased types may need further types
+        while !self.alias_todo.is_empty() {
+            let aliases = mem::take(&mut self.alias_todo);
+            self.write_aliases(&aliases)?;
+            self.write_todos()?;
+        }
         self.write_stubs()?;
         self.write_bool_types()?;
         Ok(())
     }
 
+    /// Writes the RVAs of all global and static variables of the arena as constants of a
+    /// `globals` module, ordered by name.
+    pub fn write_globals(&mut self) -> Result<()> {
+        let mut globals: Vec<_> = self.arena.globals().iter()
+            .filter_map(|global| Some((&global.name, global.rva?)))
+            .collect();
+        globals.sort_by(|(a, _), (b, _)| a.name.cmp(&b.name));
+        writeln!(self.w, "{}pub mod globals {{", self.indent)?;
+        self.indent();
+        let mut constants = HashSet::new();
+        for (name, rva) in globals {
+            // static variables of different translation units can have the same name
+            let base = constant_case(&name.ident);
+            let mut constant = base.clone();
+            let mut i = 1;
+            while !constants.insert(constant.clone()) {
+                i += 1;
+                constant = format!("{}_{}", base, i);
+            }
+            writeln!(self.w, "{}pub const {}: u32 = {:#x}; // {}", self.indent, constant, rva, name.name)?;
+        }
+        self.dedent();
+        writeln!(self.w, "{}}}", self.indent)?;
+        Ok(())
+    }
+
+    /// Writes the RVAs of the functions with the given undecorated or decorated names into a
+    /// `functions` module, together with a function returning a typed pointer to each function
+    /// of a binary loaded at `image_base`.
+    pub fn write_functions<N: AsRef<str>>(&mut self, names: &[N]) -> Result<()> {
+        let arena = self.arena;
+        let functions = names.iter()
+            .map(|name| arena.functions().get(name.as_ref()))
+            .collect::<Result<Vec<_>>>()?;
+        writeln!(self.w, "{}pub mod functions {{", self.indent)?;
+        self.indent();
+        if functions.iter().any(|function| function.signature.is_some()) {
+            writeln!(self.w, "{}use super::*;", self.indent)?;
+        }
+        let mut written = HashSet::new();
+        let mut idents = HashSet::new();
+        for function in functions {
+            let Function { name, decorated_name, rva, signature, .. } = function;
+            if !written.insert(*rva) {
+                continue;
+            }
+            // overloads have the same undecorated name
+            let base = ident(name);
+            let mut ident = base.clone();
+            let mut i = 1;
+            while !idents.insert(ident.clone()) {
+                i += 1;
+                ident = format!("{}_{}", base, i);
+            }
+            let constant = format!("{}_RVA", constant_case(&ident));
+            write!(self.w, "{}pub const {}: u32 = {:#x}; // {}", self.indent, constant, rva, name)?;
+            match decorated_name {
+                Some(decorated_name) if decorated_name != name => writeln!(self.w, " ({})", decorated_name)?,
+                _ => writeln!(self.w)?,
+            }
+            let signature = match signature {
+                Some(signature) => signature,
+                None => continue,
+            };
+            write!(self.w, "{}pub unsafe fn {}(image_base: usize) -> ", self.indent, ident)?;
+            self.write_function_type(signature)?;
+            writeln!(self.w, " {{")?;
+            self.indent();
+            writeln!(self.w, "{}std::mem::transmute(image_base + {} as usize)", self.indent, constant)?;
+            self.dedent();
+            writeln!(self.w, "{}}}", self.indent)?;
+        }
+        self.dedent();
+        writeln!(self.w, "{}}}", self.indent)?;
+        Ok(())
+    }
+
+    /// Writes a type alias for each typedef with the given name, unless it has already been
+    /// written.
+    pub fn write_aliases<N: AsRef<str>>(&mut self, names: &[N]) -> Result<()> {
+        let arena = self.arena;
+        for name in names {
+            let alias = arena.get_alias(name.as_ref())
+                .ok_or_else(|| Error::UnknownType(name.as_ref().to_string()))?;
+            if !self.written_aliases.insert(alias.name.name.clone()) {
+                continue;
+            }
+            write!(self.w, "{}pub type {} = ", self.indent, alias.name.ident)?;
+            self.write_class_field_kind(&alias.kind)?;
+            writeln!(self.w, ";")?;
+        }
+        Ok(())
+    }
+
     fn write_alignment(&mut self, alignment: Alignment) -> Result<()> {
         match alignment {
             Alignment::None => (),
@@ -161,10 +277,18 @@ impl<'a, W: Write> Writer<'a, W> {
     }
 
     fn write_class(&mut self, class: &Class) -> Result<()> {
-        let Class { name, kind, members, properties, size, alignment } = class;
+        let Class { name, kind, members, properties, derived_from: _, nested_types: _, static_members, virtual_methods, vtable_shape, size, alignment, opaque, origin } = class;
+        if *opaque {
+            return self.write_opaque(name, *size, *origin);
+        }
         self.current_type_name = Some(name.ident.clone());
-        assert_ne!(*kind, ClassKind::Interface);
-        writeln!(self.w, "{}// {}", self.indent, name.name)?;
+        self.current_vtables = virtual_methods.iter().map(|method| method.vtable_offset).collect();
+        self.current_vtables.dedup();
+        self.current_end = class.non_virtual_end(self.arena);
+        match kind {
+            ClassKind::Interface => writeln!(self.w, "{}// interface {} ({})", self.indent, name.name, origin)?,
+            _ => writeln!(self.w, "{}// {} ({})", self.indent, name.name, origin)?,
+        }
         if properties.packed {
             writeln!(self.w, "{}#[repr(C, packed)]", self.indent)?;
         } else {
@@ -180,8 +304,15 @@ impl<'a, W: Write> Writer<'a, W> {
         for member in members {
             fields.extend(self.write_class_member(member)?);
         }
+        let unknown_virtual_bases = members.iter()
+            .any(|member| matches!(member, ClassMember::VirtualBaseClass(base) if !base.offset_known));
+        if unknown_virtual_bases && *size > self.current_end {
+            writeln!(self.w, "{}_virtual_bases: [u8; {:#05x}], // offset {:#05x}", self.indent, size - self.current_end, self.current_end)?;
+            fields.push(("_virtual_bases".to_string(), Some(self.current_end)));
+        }
         self.dedent();
         self.current_type_name = None;
+        self.current_vtables = Vec::new();
         writeln!(self.w, "{}}} // size {:#05x}", self.indent, size)?;
 
         // write layout test
@@ -199,12 +330,167 @@ impl<'a, W: Write> Writer<'a, W> {
         self.dedent();
         writeln!(self.w, "{}}}", self.indent)?;
 
+        self.write_vtable_structs(name, virtual_methods, vtable_shape.as_ref())?;
+        self.write_static_member_addresses(name, static_members)?;
+        self.write_type_constants(name)
+    }
+
+    /// Writes the constants declared in the class or union as associated constants.
+    fn write_type_constants(&mut self, name: &Name) -> Result<()> {
+        let arena = self.arena;
+        if arena.constants().in_scope(&name.name).next().is_none() {
+            return Ok(());
+        }
+        writeln!(self.w, "{}impl {} {{", self.indent, name.ident)?;
+        self.indent();
+        self.write_constants_in(&name.name)?;
+        self.dedent();
+        writeln!(self.w, "{}}}", self.indent)?;
+        Ok(())
+    }
+
+    /// Writes all constants which aren't declared in a type into a module for each namespace.
+    /// The constants of classes and unions are written together with those.
+    pub fn write_constants(&mut self) -> Result<()> {
+        let arena = self.arena;
+        let mut namespaces: Vec<Vec<&str>> = arena.constants().scopes()
+            .filter(|scope| arena.get_type_by_name(scope).is_none())
+            .map(|scope| {
+                let mut components = Vec::new();
+                let mut rest = Some(scope);
+                while let Some(scope) = rest.filter(|scope| !scope.is_empty()) {
+                    let (parent, last) = split_scope(scope);
+                    components.push(last);
+                    rest = parent;
+                }
+                components.reverse();
+                components
+            })
+            .collect();
+        namespaces.sort();
+        let mut open: Vec<&str> = Vec::new();
+        for namespace in namespaces {
+            let common = open.iter().zip(&namespace).take_while(|(a, b)| a == b).count();
+            while open.len() > common {
+                open.pop();
+                self.dedent();
+                writeln!(self.w, "{}}}", self.indent)?;
+            }
+            for &component in &namespace[common..] {
+                writeln!(self.w, "{}pub mod {} {{", self.indent, ident(component))?;
+                self.indent();
+                open.push(component);
+            }
+            self.write_constants_in(&namespace.join("::"))?;
+        }
+        for _ in open {
+            self.dedent();
+            writeln!(self.w, "{}}}", self.indent)?;
+        }
+        Ok(())
+    }
+
+    fn write_constants_in(&mut self, scope: &str) -> Result<()> {
+        let arena = self.arena;
+        let mut names = HashSet::new();
+        for constant in arena.constants().in_scope(scope) {
+            let (_, name) = constant.split_name();
+            let (typ, literal) = match self.constant_literal(&constant.kind, constant.value) {
+                Some(literal) => literal,
+                None => {
+                    writeln!(self.w, "{}// skipped constant {} of unsupported type", self.indent, name)?;
+                    continue;
+                }
+            };
+            let ident = constant_case(&ident(name));
+            // the same constant can be contained multiple times
+            if names.insert(ident.clone()) {
+                writeln!(self.w, "{}pub const {}: {} = {}; // {}", self.indent, ident, typ, literal, name)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The rust type and value of a constant, which has an integer, bool or enum type.
+    fn constant_literal(&self, kind: &ClassFieldKind, value: EnumValue) -> Option<(&'static str, String)> {
+        let prim = match kind {
+            ClassFieldKind::Primitive(prim) => *prim,
+            ClassFieldKind::Enum(e) => self.arena[*e].underlying,
+            ClassFieldKind::Modifier(m) => return self.constant_literal(&m.underlying, value),
+            _ => return None,
+        };
+        primitive_literal(prim, value)
+    }
+
+    /// Writes the RVAs of the static members as associated constants.
+    fn write_static_member_addresses(&mut self, name: &Name, static_members: &[StaticMember]) -> Result<()> {
+        if static_members.iter().all(|member| member.rva.is_none()) {
+            return Ok(());
+        }
+        writeln!(self.w, "{}impl {} {{", self.indent, name.ident)?;
+        self.indent();
+        for member in static_members {
+            if let Some(rva) = member.rva {
+                writeln!(self.w, "{}pub const {}_RVA: u32 = {:#x}; // {}", self.indent, constant_case(&member.name.ident), rva, member.name.name)?;
+            }
+        }
+        self.dedent();
+        writeln!(self.w, "{}}}", self.indent)?;
+        Ok(())
+    }
+
+    /// Writes a struct for each vtable of a class, with a field for each slot.
+    /// `vtable_shape` describes the vtable at offset 0, which may have more slots than methods
+    /// known from the pdb.
+    fn write_vtable_structs(&mut self, name: &Name, virtual_methods: &[VirtualMethod], vtable_shape: Option<&VtableShape>) -> Result<()> {
+        let mut offsets: Vec<_> = virtual_methods.iter().map(|method| method.vtable_offset).collect();
+        offsets.dedup();
+        for offset in offsets {
+            writeln!(self.w, "{}// vtable of {} at offset {:#05x}", self.indent, name.name, offset)?;
+            writeln!(self.w, "{}#[repr(C)]", self.indent)?;
+            writeln!(self.w, "{}#[derive(Clone, Copy)]", self.indent)?;
+            writeln!(self.w, "{}pub struct {} {{", self.indent, vtable_struct_name(&name.ident, offset))?;
+            self.indent();
+            let mut fields: Vec<String> = Vec::new();
+            let mut next_slot = 0;
+            for method in virtual_methods.iter().filter(|method| method.vtable_offset == offset) {
+                // slots whose method isn't known, e.g. of functions without debug information
+                self.write_unknown_vtable_slots(next_slot..method.slot)?;
+                // overloads have the same name
+                let mut ident = method.name.ident.clone();
+                let mut i = 0;
+                while fields.contains(&ident) {
+                    i += 1;
+                    ident = format!("{}{}", method.name.ident, i);
+                }
+                write!(self.w, "{}pub {}: ", self.indent, ident)?;
+                self.write_field_function(&method.signature)?;
+                writeln!(self.w, ", // slot {}", method.slot)?;
+                fields.push(ident);
+                next_slot = method.slot + 1;
+            }
+            if let (0, Some(shape)) = (offset, vtable_shape) {
+                self.write_unknown_vtable_slots(next_slot..shape.slots.len())?;
+            }
+            self.dedent();
+            writeln!(self.w, "{}}}", self.indent)?;
+        }
+        Ok(())
+    }
+
+    fn write_unknown_vtable_slots(&mut self, slots: Range<usize>) -> Result<()> {
+        for slot in slots {
+            writeln!(self.w, "{}pub _unknown_{}: *const (), // slot {}", self.indent, slot, slot)?;
+        }
         Ok(())
     }
 
     fn write_union(&mut self, u: &Union) -> Result<()> {
-        let Union { name, fields, properties, size, count: _, alignment } = u;
-        writeln!(self.w, "{}// {}", self.indent, name.name)?;
+        let Union { name, fields, properties, nested_types: _, size, count: _, alignment, opaque, origin } = u;
+        if *opaque {
+            return self.write_opaque(name, *size, *origin);
+        }
+        writeln!(self.w, "{}// {} ({})", self.indent, name.name, origin)?;
         if properties.packed {
             writeln!(self.w, "{}#[repr(C, packed)]", self.indent)?;
         } else {
@@ -223,13 +509,22 @@ impl<'a, W: Write> Writer<'a, W> {
         self.dedent();
         self.current_type_name = None;
         writeln!(self.w, "{}}} // size {:#05x}", self.indent, size)?;
-        Ok(())
+        self.write_type_constants(name)
     }
 
     fn write_enum(&mut self, e: &Enum) -> Result<()> {
         let size = e.size(self.arena);
-        let Enum { name, underlying, variants, properties, count: _, alignment } = e;
-        writeln!(self.w, "{}// {}", self.indent, name.name)?;
+        let Enum { name, underlying, variants, properties, count: _, alignment, opaque, origin } = e;
+        writeln!(self.w, "{}// {} ({})", self.indent, name.name, origin)?;
+        if *opaque {
+            // we don't know the variants, so we can't generate a rust enum
+            writeln!(self.w, "{}#[repr(transparent)]", self.indent)?;
+            writeln!(self.w, "{}#[derive(Clone, Copy)]", self.indent)?;
+            write!(self.w, "{}pub struct {}(pub ", self.indent, name.ident)?;
+            self.write_field_primitive(underlying)?;
+            writeln!(self.w, "); // size {:#05x}", size)?;
+            return Ok(());
+        }
         write!(self.w, "{}#[repr(", self.indent)?;
         self.write_field_primitive(underlying)?;
         if properties.packed {
@@ -253,9 +548,19 @@ impl<'a, W: Write> Writer<'a, W> {
         Ok(())
     }
 
+    /// Writes a type which couldn't be converted as a blob of bytes of the correct size.
+    fn write_opaque(&mut self, name: &Name, size: usize, origin: Origin) -> Result<()> {
+        writeln!(self.w, "{}// {} (opaque, {})", self.indent, name.name, origin)?;
+        writeln!(self.w, "{}#[repr(C)]", self.indent)?;
+        writeln!(self.w, "{}#[derive(Clone, Copy)]", self.indent)?;
+        writeln!(self.w, "{}pub struct {}(pub [u8; {:#05x}]);", self.indent, name.ident, size)?;
+        Ok(())
+    }
+
     fn write_class_member(&mut self, member: &ClassMember) -> Result<Vec<(String, Option<usize>)>> {
         Ok(match member {
             ClassMember::Vtable => self.write_vtable()?,
+            ClassMember::VirtualBasePointer(pointer) => self.write_virtual_base_pointer(pointer)?,
             ClassMember::BaseClass(base) => self.write_base_class(base)?,
             ClassMember::VirtualBaseClass(base) => self.write_virtual_base_class(base)?,
             ClassMember::Field(field) => self.write_class_field(field)?,
@@ -266,20 +571,39 @@ impl<'a, W: Write> Writer<'a, W> {
         let name = self.current_base_class_name.as_ref()
             .or(self.current_type_name.as_ref()).unwrap();
         let name = format!("vtable_{}", name);
-        writeln!(self.w, "{}{}: *const (),", self.indent, name)?;
+        match self.current_base_offset {
+            Some(offset) if self.current_vtables.contains(&offset) => {
+                let vtable = vtable_struct_name(self.current_type_name.as_ref().unwrap(), offset);
+                writeln!(self.w, "{}{}: *const {},", self.indent, name, vtable)?;
+            }
+            _ => writeln!(self.w, "{}{}: *const (),", self.indent, name)?,
+        }
         Ok(vec![(name, None)])
     }
 
+    fn write_virtual_base_pointer(&mut self, pointer: &VirtualBasePointer) -> Result<Vec<(String, Option<usize>)>> {
+        let VirtualBasePointer { offset, kind } = pointer;
+        let name = self.current_base_class_name.as_ref()
+            .or(self.current_type_name.as_ref()).unwrap();
+        let name = format!("vbptr_{}", name);
+        write!(self.w, "{}{}: ", self.indent, name)?;
+        self.write_class_field_kind(kind)?;
+        writeln!(self.w, ", // offset {:#05x}", offset)?;
+        Ok(vec![(name, self.current_base_offset.map(|base| base + offset))])
+    }
+
     fn write_base_class(&mut self, base: &BaseClass) -> Result<Vec<(String, Option<usize>)>> {
         let BaseClass { attributes, offset, base_class } = base;
-        let base_class = self.arena.get_largest_class_index(*base_class);
-        let Class { name, kind, members, properties, size, alignment } = &self.arena[base_class];
+        let base_class = self.arena.resolve_class_index(*base_class);
+        let Class { name, kind, members, properties, size, alignment, .. } = &self.arena[base_class];
         assert_eq!(*alignment, Alignment::None, "unimplemented: BaseClass Alignment");
         if attributes.any() {
             eprintln!("found nonrelevant base class: {}", name.name);
             return Ok(vec![]);
         }
         let old_base_class_name = mem::replace(&mut self.current_base_class_name, Some(name.ident.clone()));
+        let old_base_offset = self.current_base_offset;
+        self.current_base_offset = old_base_offset.map(|old| old + offset);
         writeln!(self.w, "{}// START base class {}", self.indent, name.name)?;
         let mut names = Vec::new();
         for member in members {
@@ -287,31 +611,53 @@ impl<'a, W: Write> Writer<'a, W> {
         }
         writeln!(self.w, "{}// END base class {} // size {:#05x}", self.indent, name.name, size)?;
         self.current_base_class_name = old_base_class_name;
+        self.current_base_offset = old_base_offset;
         Ok(names)
     }
 
     fn write_virtual_base_class(&mut self, base: &VirtualBaseClass) -> Result<Vec<(String, Option<usize>)>> {
-        let VirtualBaseClass { attributes, base_pointer_offset, base_class, .. } = base;
-        let base_class = self.arena.get_largest_class_index(*base_class);
-        let Class { name, kind, members, properties, size, alignment } = &self.arena[base_class];
+        // virtual bases are only stored once at the end of the most derived class, unknown
+        // offsets are written as a whole by `write_class`
+        if self.current_base_class_name.is_some() || !base.offset_known {
+            return Ok(vec![]);
+        }
+        let VirtualBaseClass { attributes, base_class, offset, vtordisp, .. } = base;
+        let base_class = self.arena.resolve_class_index(*base_class);
+        let class = &self.arena[base_class];
+        let Class { name, members, alignment, .. } = class;
         assert_eq!(*alignment, Alignment::None, "unimplemented: VirtualBaseClass Alignment");
         if attributes.any() {
             eprintln!("found nonrelevant base class: {}", name.name);
             return Ok(vec![]);
         }
+        let mut names = Vec::new();
+        let start = if *vtordisp { offset - 4 } else { *offset };
+        if start > self.current_end {
+            let pad = format!("_pad_{}", name.ident);
+            writeln!(self.w, "{}{}: [u8; {:#05x}],", self.indent, pad, start - self.current_end)?;
+            names.push((pad, Some(self.current_end)));
+        }
+        if *vtordisp {
+            let field = format!("vtordisp_{}", name.ident);
+            writeln!(self.w, "{}{}: u32, // offset {:#05x}", self.indent, field, start)?;
+            names.push((field, Some(start)));
+        }
         let old_base_class_name = mem::replace(&mut self.current_base_class_name, Some(name.ident.clone()));
+        let old_base_offset = self.current_base_offset.replace(*offset);
         writeln!(self.w, "{}// START virtual base class {}", self.indent, name.name)?;
-        let mut names = Vec::new();
         for member in members {
             names.extend(self.write_class_member(member)?);
         }
+        let size = class.non_virtual_size(self.arena);
         writeln!(self.w, "{}// END virtual base class {} // size {:#05x}", self.indent, name.name, size)?;
         self.current_base_class_name = old_base_class_name;
+        self.current_base_offset = old_base_offset;
+        self.current_end = offset + class.non_virtual_end(self.arena);
         Ok(names)
     }
 
     fn write_class_field(&mut self, field: &ClassField) -> Result<Vec<(String, Option<usize>)>> {
-        let ClassField { attributes, name, offset, kind, max_size } = field;
+        let ClassField { attributes, name, offset, kind, .. } = field;
         if attributes.any() {
             eprintln!("found nonrelevant field: {}", name.name);
             return Ok(vec![]);
@@ -340,12 +686,21 @@ impl<'a, W: Write> Writer<'a, W> {
             self.current_fields.push(ident.clone().into_owned());
             let name = ident.into_owned();
         // };
-        self.write_class_field_kind(kind, *max_size)?;
+        // typedefs which weren't converted, e.g. because only some types were, can't be used
+        match field.alias.as_ref().and_then(|alias| self.arena.get_alias(alias)) {
+            Some(alias) => {
+                write!(self.w, "{}", alias.name.ident)?;
+                if !self.written_aliases.contains(&alias.name.name) {
+                    self.alias_todo.push(alias.name.name.clone());
+                }
+            }
+            None => self.write_class_field_kind(kind)?,
+        }
         writeln!(self.w, ", // offset {:#05x}", offset)?;
         Ok(vec![(name, Some(*offset))])
     }
 
-    fn write_class_field_kind(&mut self, kind: &ClassFieldKind, max_size: usize) -> Result<()> {
+    fn write_class_field_kind(&mut self, kind: &ClassFieldKind) -> Result<()> {
         match kind {
             ClassFieldKind::Primitive(prim) => self.write_field_primitive(prim)?,
             ClassFieldKind::Enum(e) => self.write_field_enum(*e)?,
@@ -353,12 +708,11 @@ impl<'a, W: Write> Writer<'a, W> {
             ClassFieldKind::Class(class) => self.write_field_class(*class)?,
             ClassFieldKind::Bitfield(b) => self.write_field_bitfield(b)?,
             ClassFieldKind::Union(u) => self.write_field_union(*u)?,
-            ClassFieldKind::Array(arr) => self.write_field_array(arr, max_size)?,
+            ClassFieldKind::Array(arr) => self.write_field_array(arr)?,
             ClassFieldKind::Modifier(m) => self.write_field_modifier(m)?,
-            // ignore as they aren't fields
-            ClassFieldKind::Procedure => self.write_field_function()?,
-            ClassFieldKind::MemberFunction => self.write_field_function()?,
-            ClassFieldKind::Method => self.write_field_function()?,
+            ClassFieldKind::Procedure(signature) => self.write_field_function(signature)?,
+            ClassFieldKind::MemberFunction(signature) => self.write_field_function(signature)?,
+            ClassFieldKind::Method(signature) => self.write_field_function(signature)?,
         }
         Ok(())
     }
@@ -415,13 +769,23 @@ impl<'a, W: Write> Writer<'a, W> {
 
     fn write_field_pointer(&mut self, ptr: &Pointer) -> Result<()> {
         let Pointer { underlying, is_const, .. } = ptr;
+        // e.g. pointers to member functions of classes with multiple inheritance or `__ptr32`
+        let size = ptr.size(self.arena);
+        if size != self.arena.pointer_size() {
+            write!(self.w, "[u8; {}]", size)?;
+            return Ok(());
+        }
+        // function pointers are written as nullable rust function pointers
+        if let Some(signature) = function_signature(underlying) {
+            return self.write_field_function(signature);
+        }
         if *is_const {
             write!(self.w, "*const ")?;
         } else {
             write!(self.w, "*mut ")?;
         }
         self.is_pointer_field = true;
-        self.write_class_field_kind(underlying, usize::MAX)?;
+        self.write_class_field_kind(underlying)?;
         self.is_pointer_field = false;
         Ok(())
     }
@@ -456,34 +820,14 @@ impl<'a, W: Write> Writer<'a, W> {
         Ok(())
     }
 
-    fn write_field_array(&mut self, arr: &Array, max_size: usize) -> Result<()> {
-        let Array { element_type, dimensions, stride } = arr;
+    fn write_field_array(&mut self, arr: &Array) -> Result<()> {
+        let Array { element_type, dimensions, .. } = arr;
         for _ in dimensions {
             write!(self.w, "[")?;
         }
-        let mut size = element_type.size(self.arena);
-        dbg!(size, max_size);
-        self.write_class_field_kind(element_type, usize::MAX)?;
-        dbg!(element_type, stride, dimensions);
-        let len = dimensions.len();
-        for (i, &d) in dimensions.iter().enumerate() {
-            let d = if i == len-1 && max_size < usize::MAX && max_size > d {
-                // last element
-                // The pdb sometimes reports wrong dimensions (number of elements instead of number
-                // of bytes). Thus, for the last element we use max_size instead of the dimension.
-                if max_size != d {
-                    eprintln!("PDB reported invalid array dimension: {} instead of max {}", d, max_size);
-                }
-                max_size
-            } else {
-                if max_size == usize::MAX {
-                    eprintln!("Unknown Array max_size");
-                }
-                d
-            };
-            let num_elements = d / size.max(1);
-            write!(self.w, "; {}]", num_elements)?;
-            size = d;
+        self.write_class_field_kind(element_type)?;
+        for d in dimensions {
+            write!(self.w, "; {}]", d)?;
         }
         Ok(())
     }
@@ -493,12 +837,70 @@ impl<'a, W: Write> Writer<'a, W> {
 //        if *constant {
 //            write!(self.w, "const ")?;
 //        }
-        self.write_class_field_kind(underlying, usize::MAX)
+        self.write_class_field_kind(underlying)
     }
 
-    fn write_field_function(&mut self) -> Result<()> {
-        // TODO: actual function types
-        write!(self.w, "fn()")?;
+    fn write_field_function(&mut self, signature: &Signature) -> Result<()> {
+        if let Err(reason) = self.abi(signature) {
+            return self.write_untyped_function(reason);
+        }
+        write!(self.w, "Option<")?;
+        self.write_function_type(signature)?;
+        write!(self.w, ">")?;
+        Ok(())
+    }
+
+    /// The ABI of a function type, or why rust can't express the function type.
+    fn abi(&self, signature: &Signature) -> std::result::Result<&'static str, &'static str> {
+        // all conventions are the same outside of x86
+        let abi = match (self.arena.machine_type(), signature.calling_convention) {
+            (MachineType::X86, CallingConvention::Stdcall | CallingConvention::Syscall) => "system",
+            (MachineType::X86, CallingConvention::Thiscall) => "thiscall",
+            (MachineType::X86, CallingConvention::Fastcall) => "fastcall",
+            // `extern "vectorcall"` isn't stable
+            (MachineType::X86, CallingConvention::Vectorcall) => return Err("vectorcall"),
+            _ => "C",
+        };
+        // rust only supports variadic functions with the C calling convention
+        if signature.variadic && abi != "C" {
+            return Err("variadic");
+        }
+        Ok(abi)
+    }
+
+    /// Functions whose type can't be written are untyped pointers.
+    fn write_untyped_function(&mut self, reason: &str) -> Result<()> {
+        write!(self.w, "*const () /* {} */", reason)?;
+        Ok(())
+    }
+
+    fn write_function_type(&mut self, signature: &Signature) -> Result<()> {
+        let Signature { return_type, arguments, calling_convention: _, this_type, variadic } = signature;
+        let abi = match self.abi(signature) {
+            Ok(abi) => abi,
+            Err(reason) => return self.write_untyped_function(reason),
+        };
+        // arguments and return value are passed by value, so they need the full types
+        let is_pointer_field = mem::replace(&mut self.is_pointer_field, false);
+        write!(self.w, "unsafe extern \"{}\" fn(", abi)?;
+        for (i, argument) in this_type.iter().chain(arguments).enumerate() {
+            if i > 0 {
+                write!(self.w, ", ")?;
+            }
+            self.write_class_field_kind(argument)?;
+        }
+        if *variadic {
+            if this_type.is_some() || !arguments.is_empty() {
+                write!(self.w, ", ")?;
+            }
+            write!(self.w, "...")?;
+        }
+        write!(self.w, ")")?;
+        if !matches!(return_type, ClassFieldKind::Primitive(PrimitiveKind::Void)) {
+            write!(self.w, " -> ")?;
+            self.write_class_field_kind(return_type)?;
+        }
+        self.is_pointer_field = is_pointer_field;
         Ok(())
     }
 
@@ -520,6 +922,69 @@ impl<'a, W: Write> Writer<'a, W> {
     }
 }
 
+/// Converts a camel case identifier to upper snake case, e.g. `StaticFoo` to `STATIC_FOO`.
+/// The rust type and value of a constant of primitive type `prim`.
+fn primitive_literal(prim: PrimitiveKind, value: EnumValue) -> Option<(&'static str, String)> {
+    let value = match value {
+        EnumValue::U8(v) => v as i128,
+        EnumValue::U16(v) => v as i128,
+        EnumValue::U32(v) => v as i128,
+        EnumValue::U64(v) => v as i128,
+        EnumValue::I8(v) => v as i128,
+        EnumValue::I16(v) => v as i128,
+        EnumValue::I32(v) => v as i128,
+        EnumValue::I64(v) => v as i128,
+    };
+    let (typ, bits, signed) = match prim {
+        PrimitiveKind::Bool8 | PrimitiveKind::Bool16 | PrimitiveKind::Bool32 | PrimitiveKind::Bool64 =>
+            return Some(("bool", (value != 0).to_string())),
+        PrimitiveKind::Char | PrimitiveKind::RChar | PrimitiveKind::I8 => ("i8", 8, true),
+        PrimitiveKind::UChar | PrimitiveKind::U8 => ("u8", 8, false),
+        PrimitiveKind::RChar16 | PrimitiveKind::U16 => ("u16", 16, false),
+        PrimitiveKind::WChar | PrimitiveKind::RChar32 | PrimitiveKind::U32 => ("u32", 32, false),
+        PrimitiveKind::I16 => ("i16", 16, true),
+        PrimitiveKind::I32 => ("i32", 32, true),
+        PrimitiveKind::I64 => ("i64", 64, true),
+        PrimitiveKind::U64 => ("u64", 64, false),
+        _ => return None,
+    };
+    // the pdb stores the value in the smallest encoding, e.g. -1 for `0xffffffffu`
+    let value = value & ((1 << bits) - 1);
+    let value = if signed && value >= 1 << (bits - 1) { value - (1 << bits) } else { value };
+    Some((typ, value.to_string()))
+}
+
+fn constant_case(ident: &str) -> String {
+    let mut res = String::with_capacity(ident.len() + 4);
+    let mut previous_lowercase = false;
+    for c in ident.chars() {
+        if c.is_uppercase() && previous_lowercase {
+            res.push('_');
+        }
+        previous_lowercase = c.is_lowercase() || c.is_ascii_digit();
+        res.extend(c.to_uppercase());
+    }
+    res
+}
+
+fn vtable_struct_name(ident: &str, offset: usize) -> String {
+    match offset {
+        0 => format!("{}_vtbl", ident),
+        offset => format!("{}_vtbl_{:x}", ident, offset),
+    }
+}
+
+/// The signature of a function type, looking through modifiers.
+fn function_signature(kind: &ClassFieldKind) -> Option<&Signature> {
+    match kind {
+        ClassFieldKind::Procedure(signature)
+        | ClassFieldKind::MemberFunction(signature)
+        | ClassFieldKind::Method(signature) => Some(signature),
+        ClassFieldKind::Modifier(m) => function_signature(&m.underlying),
+        _ => None,
+    }
+}
+
 fn bool_fmt(size: u8) -> String {
     format!(r#"#[repr(transparent)]
 #[derive(Debug, Clone, Copy)]
@@ -534,3 +999,36 @@ impl From<Bool{0}> for bool {{
     }}
 }}"#, size)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn primitive_literals() {
+        assert_eq!(primitive_literal(PrimitiveKind::I32, EnumValue::I8(-5)), Some(("i32", "-5".to_string())));
+        assert_eq!(primitive_literal(PrimitiveKind::U16, EnumValue::U16(0x1234)), Some(("u16", "4660".to_string())));
+        assert_eq!(primitive_literal(PrimitiveKind::I64, EnumValue::I64(i64::MIN)), Some(("i64", i64::MIN.to_string())));
+        assert_eq!(primitive_literal(PrimitiveKind::U64, EnumValue::U64(u64::MAX)), Some(("u64", u64::MAX.to_string())));
+        assert_eq!(primitive_literal(PrimitiveKind::Bool8, EnumValue::U16(1)), Some(("bool", "true".to_string())));
+        assert_eq!(primitive_literal(PrimitiveKind::Bool32, EnumValue::U16(0)), Some(("bool", "false".to_string())));
+    }
+
+    #[test]
+    fn primitive_literals_reinterpret_the_smallest_encoding() {
+        // `0xffffffffu` is stored as LF_CHAR -1
+        assert_eq!(primitive_literal(PrimitiveKind::U32, EnumValue::I8(-1)), Some(("u32", "4294967295".to_string())));
+        assert_eq!(primitive_literal(PrimitiveKind::U64, EnumValue::I64(-1)), Some(("u64", u64::MAX.to_string())));
+        // and `(char)0xff` as LF_USHORT 255
+        assert_eq!(primitive_literal(PrimitiveKind::Char, EnumValue::U16(0xff)), Some(("i8", "-1".to_string())));
+        assert_eq!(primitive_literal(PrimitiveKind::I16, EnumValue::U16(0x8000)), Some(("i16", "-32768".to_string())));
+        // values too large for the type are truncated
+        assert_eq!(primitive_literal(PrimitiveKind::U8, EnumValue::U32(0x1ff)), Some(("u8", "255".to_string())));
+    }
+
+    #[test]
+    fn unsupported_primitive_literals() {
+        assert_eq!(primitive_literal(PrimitiveKind::F32, EnumValue::U16(1)), None);
+        assert_eq!(primitive_literal(PrimitiveKind::Void, EnumValue::U16(0)), None);
+    }
+}
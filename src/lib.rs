@@ -1,9 +1,21 @@
+use std::fmt::{self, Debug};
 use std::fs::File;
-use std::path::Path;
-use pdb::PDB;
-use crate::ir::{Arena, Converter};
+use std::io::{Cursor, Read, Seek};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use pdb::{PDB, Source, TypeInformation};
+use crate::dbi::DbiHeader;
+use crate::symbols::Data;
+use crate::tpi::RawTypes;
+use crate::ir::{Arena, Converter, DuplicatePolicy, Phase, Progress, Report, TypeTable};
 
 pub mod ir;
+pub mod pe;
+mod dbi;
+mod tpi;
+mod symbols;
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
@@ -13,6 +25,50 @@ pub enum Error {
     Unimplemented(String),
     #[error("error during writing: {0}")]
     WriteError(#[from] std::io::Error),
+    #[error("unexpected {kind} at pdb type {index:#x} in {class}, expected {expected}")]
+    UnexpectedType {
+        index: pdb::TypeIndex,
+        kind: &'static str,
+        expected: &'static str,
+        class: String,
+    },
+    #[error("can't read pdb type {index:#x} ({kind}) in {class}: {source}")]
+    UnreadableType {
+        index: pdb::TypeIndex,
+        kind: String,
+        class: String,
+        source: pdb::Error,
+    },
+    #[error("no type named {0} in pdb")]
+    UnknownType(String),
+    #[error("no function named {0} in pdb")]
+    UnknownFunction(String),
+    #[error("{name} is ambiguous, use one of the decorated names {candidates:?}")]
+    AmbiguousFunction {
+        name: String,
+        candidates: Vec<String>,
+    },
+    #[error("{name} is defined by multiple pdb types: {candidates:x?}")]
+    DuplicateType {
+        name: String,
+        /// `None` for synthesized types.
+        candidates: Vec<Option<pdb::TypeIndex>>,
+    },
+    #[error("unexpected layout: {0}")]
+    UnexpectedLayout(String),
+    #[error("can't determine the offsets of the virtual bases of {0}")]
+    VirtualBaseLayout(String),
+    #[error("invalid PE file: {0}")]
+    InvalidPe(&'static str),
+    #[error("binary doesn't contain a CodeView debug record")]
+    NoCodeView,
+    #[error("no pdb found, tried {0:?}")]
+    PdbNotFound(Vec<PathBuf>),
+    #[error("pdb doesn't match binary: expected {expected}, found {found}")]
+    PdbMismatch {
+        expected: String,
+        found: String,
+    },
 }
 
 pub type Result<T> = ::std::result::Result<T, Error>;
@@ -25,14 +81,193 @@ pub enum Alignment {
     Linux(usize),
 }
 
-// TODO: what happens with recursive classes?
+/// Target architecture of a pdb, taken from the DBI stream header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MachineType {
+    X86,
+    Amd64,
+    Arm,
+    Arm64,
+    Ia64,
+    Unknown(u16),
+}
+
+impl MachineType {
+    pub fn from_raw(machine: u16) -> MachineType {
+        match machine {
+            0x014c => MachineType::X86,
+            0x8664 => MachineType::Amd64,
+            0x01c0 | 0x01c2 | 0x01c4 => MachineType::Arm,
+            0xaa64 => MachineType::Arm64,
+            0x0200 => MachineType::Ia64,
+            m => MachineType::Unknown(m),
+        }
+    }
+
+    pub fn pointer_size(self) -> usize {
+        match self {
+            MachineType::Amd64 | MachineType::Arm64 | MachineType::Ia64 => 8,
+            MachineType::X86 | MachineType::Arm | MachineType::Unknown(_) => 4,
+        }
+    }
+}
+
+/// Settings for `parse_with_options`.
+#[derive(Clone)]
+pub struct Options {
+    /// Replace types which can't be converted with opaque types instead of aborting,
+    /// see `parse_lenient`.
+    pub lenient: bool,
+    /// Number of threads used for the conversion. The result is the same for any number.
+    pub threads: usize,
+    pub progress: Option<Arc<dyn Progress>>,
+    /// Which type a name refers to if multiple types have the same name.
+    pub duplicate_policy: DuplicatePolicy,
+    /// Fail to convert classes whose virtual bases don't fit into the class as MSVC would
+    /// place them, instead of writing the virtual bases as opaque bytes.
+    pub strict_virtual_bases: bool,
+}
+
+impl fmt::Debug for Options {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Options")
+            .field("lenient", &self.lenient)
+            .field("threads", &self.threads)
+            .field("progress", &self.progress.is_some())
+            .field("duplicate_policy", &self.duplicate_policy)
+            .field("strict_virtual_bases", &self.strict_virtual_bases)
+            .finish()
+    }
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            lenient: false,
+            threads: thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+            progress: None,
+            duplicate_policy: DuplicatePolicy::default(),
+            strict_virtual_bases: false,
+        }
+    }
+}
 
 pub fn parse<P: AsRef<Path>>(path: P) -> Result<Arena> {
-    let mut arena = Arena::new();
-    let file = File::open(path)?;
-    let mut pdb = PDB::open(file)?;
-    let mut info = pdb.type_information()?;
-    let mut converter = Converter::new(&mut info, &mut arena)?;
-    converter.populate()?;
+    parse_from_reader(File::open(path)?)
+}
+
+/// Converts only the types with the given names and their dependencies, which is a lot faster
+/// than `parse` for large pdbs.
+/// Types only used behind pointers are contained as opaque types.
+pub fn parse_types<P: AsRef<Path>, N: AsRef<str>>(path: P, names: &[N]) -> Result<Arena> {
+    parse_types_from_reader(File::open(path)?, names)
+}
+
+pub fn parse_types_from_reader<R: Read + Seek + Debug, N: AsRef<str>>(reader: R, names: &[N]) -> Result<Arena> {
+    let (arena, _) = parse_types_with_options_from_reader(reader, names, &Options::default())?;
+    Ok(arena)
+}
+
+/// Like `parse_types`, with the settings of `parse_with_options`. The conversion always runs
+/// on the calling thread.
+pub fn parse_types_with_options<P: AsRef<Path>, N: AsRef<str>>(path: P, names: &[N], options: &Options) -> Result<(Arena, Report)> {
+    parse_types_with_options_from_reader(File::open(path)?, names, options)
+}
+
+pub fn parse_types_with_options_from_reader<R: Read + Seek + Debug, N: AsRef<str>>(reader: R, names: &[N], options: &Options) -> Result<(Arena, Report)> {
+    let mut pdb = PDB::open(reader)?;
+    let (mut arena, mut raw) = prepare(&mut pdb)?;
+    let info = pdb.type_information()?;
+    let table = type_table(&info, &mut raw, options)?;
+    let mut converter = Converter::new(&table, &mut arena);
+    converter.set_lenient(options.lenient);
+    for (i, name) in names.iter().enumerate() {
+        converter.convert_by_name(name.as_ref())?;
+        if let Some(progress) = &options.progress {
+            progress.update(Phase::Converting, i + 1, names.len());
+        }
+    }
+    let report = converter.into_report();
+    finish(&mut arena, raw, &symbols::read_data(&mut pdb)?, options)?;
+    Ok((arena, report))
+}
+
+/// Finds the pdb of the given executable or dll (see `pe::find_pdb`), makes sure that it
+/// matches the binary and parses it.
+pub fn parse_for_binary<P: AsRef<Path>, D: AsRef<Path>>(binary: P, symbol_dirs: &[D]) -> Result<Arena> {
+    parse(pe::find_pdb(binary, symbol_dirs)?)
+}
+
+/// Parses a pdb from any seekable source, e.g. a file inside an archive.
+pub fn parse_from_reader<R: Read + Seek + Debug>(reader: R) -> Result<Arena> {
+    let (arena, _) = convert(PDB::open(reader)?, &Options::default())?;
     Ok(arena)
 }
+
+/// Parses a pdb which is already loaded into memory.
+pub fn parse_from_slice(data: &[u8]) -> Result<Arena> {
+    parse_from_reader(Cursor::new(data))
+}
+
+/// Like `parse`, but never aborts on types which can't be converted.
+/// Those types are replaced by opaque types and listed in the returned `Report`.
+pub fn parse_lenient<P: AsRef<Path>>(path: P) -> Result<(Arena, Report)> {
+    parse_lenient_from_reader(File::open(path)?)
+}
+
+pub fn parse_lenient_from_reader<R: Read + Seek + Debug>(reader: R) -> Result<(Arena, Report)> {
+    convert(PDB::open(reader)?, &Options { lenient: true, ..Options::default() })
+}
+
+/// The returned `Report` is empty unless `options.lenient` is set.
+pub fn parse_with_options<P: AsRef<Path>>(path: P, options: &Options) -> Result<(Arena, Report)> {
+    parse_with_options_from_reader(File::open(path)?, options)
+}
+
+pub fn parse_with_options_from_reader<R: Read + Seek + Debug>(reader: R, options: &Options) -> Result<(Arena, Report)> {
+    convert(PDB::open(reader)?, options)
+}
+
+/// Reads the information which the pdb crate doesn't provide.
+fn prepare<'s, S: Source<'s> + 's>(pdb: &mut PDB<'s, S>) -> Result<(Arena, RawTypes)> {
+    let machine_type = MachineType::from_raw(DbiHeader::read(pdb)?.machine_type);
+    Ok((Arena::new(machine_type), tpi::read(pdb)?))
+}
+
+/// Creates the table of the pdb types, configured by `options`.
+fn type_table<'t, 's>(info: &'t TypeInformation<'s>, raw: &mut RawTypes, options: &Options) -> Result<TypeTable<'t>> {
+    let mut table = TypeTable::new(info, &raw.unique_names, options.progress.clone())?;
+    table.set_vtable_shapes(mem::take(&mut raw.vtable_shapes));
+    table.set_derived_lists(mem::take(&mut raw.derived_lists));
+    table.set_strict_virtual_bases(options.strict_virtual_bases);
+    Ok(table)
+}
+
+/// Adds what's needed to look up the converted types by name and to write them.
+fn finish(arena: &mut Arena, raw: RawTypes, data: &[Data], options: &Options) -> Result<()> {
+    arena.set_unique_names(raw.unique_names);
+    arena.set_static_member_addresses(&symbols::addresses(data));
+    arena.set_duplicate_policy(options.duplicate_policy.clone())
+}
+
+fn convert<'s, S: Source<'s> + 's>(mut pdb: PDB<'s, S>, options: &Options) -> Result<(Arena, Report)> {
+    let (arena, mut raw) = prepare(&mut pdb)?;
+    let info = pdb.type_information()?;
+    let mut table = type_table(&info, &mut raw, options)?;
+    let symbols = symbols::read(&mut pdb, options.progress.as_deref())?;
+    table.set_typedefs(&symbols.udts);
+    let (mut arena, mut report) = table.populate_parallel(arena.machine_type(), options.threads, options.lenient)?;
+    let mut converter = Converter::new(&table, &mut arena);
+    converter.set_lenient(options.lenient);
+    let globals = converter.convert_globals(&symbols.data)?;
+    let functions = converter.convert_functions(&symbols.procedures, &symbols.publics)?;
+    let aliases = converter.convert_aliases(&symbols.udts)?;
+    let constants = converter.convert_constants(&symbols.constants)?;
+    report.merge(converter.into_report());
+    arena.set_globals(globals);
+    arena.set_functions(functions);
+    arena.set_aliases(aliases);
+    arena.set_constants(constants);
+    finish(&mut arena, raw, &symbols.data, options)?;
+    Ok((arena, report))
+}
@@ -1,4 +1,6 @@
-use std::io;
+use std::io::{self, IsTerminal, Write};
+use std::process;
+use std::sync::{Arc, Mutex};
 use clap::Parser;
 use pdbextract::Alignment;
 use pdbextract::ir::*;
@@ -14,12 +16,100 @@ struct Args {
     replace: Vec<String>,
     #[clap(long)]
     recursive: bool,
+    /// Replace types which can't be converted with opaque types instead of aborting
+    #[clap(long)]
+    lenient: bool,
+    /// Symbol store directories to search for the pdb if `file` is an executable or dll
+    #[clap(long)]
+    symbol_dir: Vec<String>,
+    /// Number of threads used for the conversion, defaults to the number of cpus
+    #[clap(long)]
+    threads: Option<usize>,
+    /// Which of several types with the same name to use. Picking types by pdb index or unique
+    /// name is only supported by the library, see `DuplicatePolicy`
+    #[clap(long, default_value = "largest", possible_values = ["largest", "first", "last", "error"])]
+    duplicates: String,
+    /// Fail on classes whose virtual bases can't be placed instead of writing them as opaque bytes
+    #[clap(long)]
+    strict_virtual_bases: bool,
+    /// Don't show a progress bar
+    #[clap(long)]
+    quiet: bool,
+}
+
+/// Shows the percentage of each phase in a single line on stderr.
+#[derive(Default)]
+struct ProgressBar {
+    percentages: Mutex<Vec<(Phase, usize)>>,
+}
+
+impl Progress for ProgressBar {
+    fn update(&self, phase: Phase, done: usize, total: usize) {
+        let percentage = if total == 0 { 100 } else { done * 100 / total };
+        let mut percentages = self.percentages.lock().unwrap();
+        match percentages.iter_mut().find(|(p, _)| *p == phase) {
+            // only redraw if something visibly changed
+            Some((_, old)) if *old == percentage => return,
+            Some((_, old)) => *old = percentage,
+            None => percentages.push((phase, percentage)),
+        }
+        let line: Vec<_> = percentages.iter()
+            .map(|(phase, percentage)| {
+                let bar = "#".repeat(percentage / 5) + &".".repeat(20 - percentage / 5);
+                format!("{phase} [{bar}] {percentage:3}%")
+            })
+            .collect();
+        let mut stderr = io::stderr().lock();
+        let _ = write!(stderr, "\r{}", line.join("  "));
+        let _ = stderr.flush();
+    }
+}
+
+fn fail(error: pdbextract::Error) -> ! {
+    eprintln!("error: {}", error);
+    process::exit(1);
 }
 
 fn main() {
     env_logger::init();
     let args = Args::parse();
-    let mut arena = pdbextract::parse(&args.file).unwrap();
+    let is_binary = [".exe", ".dll"].iter().any(|ext| args.file.to_lowercase().ends_with(ext));
+    let file = if is_binary {
+        let pdb = pdbextract::pe::find_pdb(&args.file, &args.symbol_dir).unwrap_or_else(|e| fail(e));
+        eprintln!("using {}", pdb.display());
+        pdb
+    } else {
+        args.file.clone().into()
+    };
+    let duplicate_policy = match args.duplicates.as_str() {
+        "largest" => DuplicatePolicy::Largest,
+        "first" => DuplicatePolicy::First,
+        "last" => DuplicatePolicy::Last,
+        "error" => DuplicatePolicy::Error,
+        // rejected by clap
+        _ => unreachable!(),
+    };
+    let mut options = pdbextract::Options {
+        lenient: args.lenient,
+        duplicate_policy,
+        strict_virtual_bases: args.strict_virtual_bases,
+        ..Default::default()
+    };
+    if let Some(threads) = args.threads {
+        options.threads = threads;
+    }
+    let show_progress = !args.quiet && io::stderr().is_terminal();
+    if show_progress {
+        options.progress = Some(Arc::new(ProgressBar::default()));
+    }
+    let parsed = pdbextract::parse_with_options(&file, &options);
+    if show_progress {
+        eprintln!();
+    }
+    let (mut arena, report) = parsed.unwrap_or_else(|e| fail(e));
+    for failure in &report.failures {
+        eprintln!("failed to convert {}", failure);
+    }
     eprintln!("parsed");
     // let character = get_class(&arena, "TTypeCompatibleBytes<unsigned int>");
     // panic!("{}, {}", character.size, character.size(&arena));
@@ -185,9 +275,10 @@ fn padding(pad_num: usize, size: usize, offset: usize) -> ClassMember {
         offset,
         kind: ClassFieldKind::Array(Box::new(Array {
             element_type: ClassFieldKind::Primitive(PrimitiveKind::U8),
-            stride: None,
+            indexing_type: PrimitiveKind::U64,
             dimensions: vec![size],
         })),
+        alias: None,
         max_size: size,
     })
 }
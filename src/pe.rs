@@ -0,0 +1,299 @@
+//! Finding the pdb belonging to an executable or dll.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use pdb::{PDB, Source};
+use uuid::Uuid;
+
+use crate::dbi::{DbiHeader, read_u16, read_u32};
+use crate::{Error, Result};
+
+const IMAGE_DIRECTORY_ENTRY_DEBUG: usize = 6;
+const IMAGE_DEBUG_TYPE_CODEVIEW: u32 = 2;
+
+/// The CodeView (`RSDS`) record of a PE file, identifying the pdb which was
+/// created together with the binary.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodeView {
+    pub guid: Uuid,
+    pub age: u32,
+    /// Path of the pdb at link time.
+    pub path: String,
+}
+
+impl CodeView {
+    pub fn read<P: AsRef<Path>>(binary: P) -> Result<CodeView> {
+        CodeView::from_pe(&fs::read(binary)?)
+    }
+
+    pub fn from_pe(data: &[u8]) -> Result<CodeView> {
+        CodeView::parse(data).map_err(|e| match e {
+            Error::Pdb(pdb::Error::UnexpectedEof) => Error::InvalidPe("unexpected end of file"),
+            e => e,
+        })
+    }
+
+    fn parse(data: &[u8]) -> Result<CodeView> {
+        if data.get(..2) != Some(b"MZ") {
+            return Err(Error::InvalidPe("missing MZ signature"));
+        }
+        let pe = read_u32(data, 0x3c)? as usize;
+        if data.get(pe..pe + 4) != Some(b"PE\0\0") {
+            return Err(Error::InvalidPe("missing PE signature"));
+        }
+        let coff = pe + 4;
+        let number_of_sections = read_u16(data, coff + 2)? as usize;
+        let optional = coff + 20;
+        let optional_size = read_u16(data, coff + 16)? as usize;
+        let (rva_count, directories) = match read_u16(data, optional)? {
+            // PE32
+            0x10b => (read_u32(data, optional + 92)?, optional + 96),
+            // PE32+
+            0x20b => (read_u32(data, optional + 108)?, optional + 112),
+            _ => return Err(Error::InvalidPe("unknown optional header magic")),
+        };
+        if rva_count as usize <= IMAGE_DIRECTORY_ENTRY_DEBUG {
+            return Err(Error::NoCodeView);
+        }
+        let debug_directory = directories + IMAGE_DIRECTORY_ENTRY_DEBUG * 8;
+        let debug_rva = read_u32(data, debug_directory)?;
+        let debug_size = read_u32(data, debug_directory + 4)? as usize;
+        if debug_rva == 0 {
+            return Err(Error::NoCodeView);
+        }
+
+        // find the section containing the debug directory to get its file offset
+        let sections = optional + optional_size;
+        let debug_offset = (0..number_of_sections)
+            .map(|i| sections + i * 40)
+            .find_map(|section| {
+                let virtual_size = read_u32(data, section + 8).ok()?;
+                let virtual_address = read_u32(data, section + 12).ok()?;
+                let raw_size = read_u32(data, section + 16).ok()?;
+                let raw_pointer = read_u32(data, section + 20).ok()?;
+                let end = virtual_address.checked_add(virtual_size.max(raw_size))?;
+                (virtual_address..end).contains(&debug_rva)
+                    .then(|| (debug_rva - virtual_address).checked_add(raw_pointer))
+                    .flatten()
+                    .map(|offset| offset as usize)
+            })
+            .ok_or(Error::InvalidPe("debug directory outside of all sections"))?;
+
+        for entry in (0..debug_size / 28).map(|i| debug_offset + i * 28) {
+            if read_u32(data, entry + 12)? != IMAGE_DEBUG_TYPE_CODEVIEW {
+                continue;
+            }
+            let size = read_u32(data, entry + 16)? as usize;
+            let offset = read_u32(data, entry + 24)? as usize;
+            let record = data.get(offset..offset + size)
+                .ok_or(Error::InvalidPe("CodeView record out of bounds"))?;
+            // older NB10 records don't contain a GUID
+            if record.get(..4) != Some(b"RSDS") {
+                continue;
+            }
+            let guid = Uuid::from_fields(
+                read_u32(record, 4)?,
+                read_u16(record, 8)?,
+                read_u16(record, 10)?,
+                record.get(12..20).ok_or(Error::InvalidPe("CodeView record too short"))?,
+            ).map_err(|_| Error::InvalidPe("invalid CodeView GUID"))?;
+            let age = read_u32(record, 20)?;
+            let path = &record[24..];
+            let path = &path[..path.iter().position(|&b| b == 0).unwrap_or(path.len())];
+            return Ok(CodeView {
+                guid,
+                age,
+                path: String::from_utf8_lossy(path).into_owned(),
+            });
+        }
+        Err(Error::NoCodeView)
+    }
+
+    /// File name of the pdb, independent of the path separators used when linking.
+    pub fn pdb_name(&self) -> &str {
+        self.path.rsplit(['\\', '/']).next().unwrap()
+    }
+
+    /// Directory name used by symbol stores (`name.pdb/<key>/name.pdb`).
+    pub fn symstore_key(&self) -> String {
+        format!("{}{:X}", self.guid.simple().to_string().to_uppercase(), self.age)
+    }
+
+    /// Checks that the given pdb is the one the binary was linked with.
+    pub fn validate<'s, S: Source<'s> + 's>(&self, pdb: &mut PDB<'s, S>) -> Result<()> {
+        let guid = pdb.pdb_information()?.guid;
+        let age = DbiHeader::read(pdb)?.age;
+        if guid != self.guid || age != self.age {
+            return Err(Error::PdbMismatch {
+                expected: self.symstore_key(),
+                found: format!("{}{:X}", guid.simple().to_string().to_uppercase(), age),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Locates the matching pdb of a binary.
+///
+/// The pdb is searched next to the binary, at the path embedded in the binary and in the given
+/// directories using the symbol store layout `name.pdb/<GUID><age>/name.pdb`.
+/// The first pdb whose GUID and age match the binary is returned.
+pub fn find_pdb<P: AsRef<Path>, D: AsRef<Path>>(binary: P, symbol_dirs: &[D]) -> Result<PathBuf> {
+    let binary = binary.as_ref();
+    let codeview = CodeView::read(binary)?;
+    let name = codeview.pdb_name();
+    let mut candidates = vec![
+        binary.with_file_name(name),
+        PathBuf::from(&codeview.path),
+    ];
+    for dir in symbol_dirs {
+        candidates.push(dir.as_ref().join(name).join(codeview.symstore_key()).join(name));
+    }
+
+    let mut mismatch = None;
+    for candidate in &candidates {
+        if !candidate.is_file() {
+            continue;
+        }
+        let validated = fs::File::open(candidate).map_err(Error::from)
+            .and_then(|file| Ok(PDB::open(file)?))
+            .and_then(|mut pdb| codeview.validate(&mut pdb));
+        match validated {
+            Ok(()) => return Ok(candidate.clone()),
+            Err(e @ Error::PdbMismatch { .. }) => {
+                log::warn!("ignoring {}: {}", candidate.display(), e);
+                mismatch.get_or_insert(e);
+            }
+            // e.g. an unreadable file or no pdb at all
+            Err(e) => log::warn!("ignoring {}: {}", candidate.display(), e),
+        }
+    }
+    Err(mismatch.unwrap_or(Error::PdbNotFound(candidates)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEBUG_DIRECTORY: usize = 0x200;
+    const RECORD: usize = 0x300;
+
+    fn put_u16(data: &mut [u8], offset: usize, value: u16) {
+        data[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn put_u32(data: &mut [u8], offset: usize, value: u32) {
+        data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    /// A PE32+ image with a single section containing the debug directory, whose CodeView
+    /// entry points to `record`.
+    fn pe(record: &[u8]) -> Vec<u8> {
+        let mut data = vec![0; RECORD + record.len()];
+        data[..2].copy_from_slice(b"MZ");
+        put_u32(&mut data, 0x3c, 0x40);
+        data[0x40..0x44].copy_from_slice(b"PE\0\0");
+        let coff = 0x44;
+        put_u16(&mut data, coff + 2, 1);
+        let optional = coff + 20;
+        let optional_size = 112 + 16 * 8;
+        put_u16(&mut data, coff + 16, optional_size as u16);
+        put_u16(&mut data, optional, 0x20b);
+        put_u32(&mut data, optional + 108, 16);
+        let debug_directory = optional + 112 + IMAGE_DIRECTORY_ENTRY_DEBUG * 8;
+        put_u32(&mut data, debug_directory, 0x1000);
+        put_u32(&mut data, debug_directory + 4, 28);
+        let section = optional + optional_size;
+        put_u32(&mut data, section + 8, 0x100);
+        put_u32(&mut data, section + 12, 0x1000);
+        put_u32(&mut data, section + 16, 0x100);
+        put_u32(&mut data, section + 20, DEBUG_DIRECTORY as u32);
+        put_u32(&mut data, DEBUG_DIRECTORY + 12, IMAGE_DEBUG_TYPE_CODEVIEW);
+        put_u32(&mut data, DEBUG_DIRECTORY + 16, record.len() as u32);
+        put_u32(&mut data, DEBUG_DIRECTORY + 24, RECORD as u32);
+        data[RECORD..].copy_from_slice(record);
+        data
+    }
+
+    fn rsds(age: u32, path: &str) -> Vec<u8> {
+        let mut record = b"RSDS".to_vec();
+        record.extend(0x2b3c3fa5u32.to_le_bytes());
+        record.extend(0x5a2eu16.to_le_bytes());
+        record.extend(0x44b8u16.to_le_bytes());
+        record.extend([0x8b, 0xba, 0xc3, 0x30, 0x0f, 0xf6, 0x9f, 0x62]);
+        record.extend(age.to_le_bytes());
+        record.extend(path.as_bytes());
+        record.push(0);
+        record
+    }
+
+    #[test]
+    fn codeview() {
+        let codeview = CodeView::from_pe(&pe(&rsds(1, r"C:\build\foo.pdb"))).unwrap();
+        assert_eq!(codeview.guid.hyphenated().to_string(), "2b3c3fa5-5a2e-44b8-8bba-c3300ff69f62");
+        assert_eq!(codeview.age, 1);
+        assert_eq!(codeview.path, r"C:\build\foo.pdb");
+        assert_eq!(codeview.pdb_name(), "foo.pdb");
+        assert_eq!(codeview.symstore_key(), "2B3C3FA55A2E44B88BBAC3300FF69F621");
+    }
+
+    #[test]
+    fn symstore_key_age() {
+        let codeview = CodeView::from_pe(&pe(&rsds(0x1a2, "/build/bar.pdb"))).unwrap();
+        assert_eq!(codeview.pdb_name(), "bar.pdb");
+        assert_eq!(codeview.symstore_key(), "2B3C3FA55A2E44B88BBAC3300FF69F621A2");
+    }
+
+    #[test]
+    fn truncated_record() {
+        let record = rsds(1, "foo.pdb");
+        assert!(matches!(CodeView::from_pe(&pe(&record[..16])), Err(Error::InvalidPe("CodeView record too short"))));
+        assert!(matches!(CodeView::from_pe(&pe(&record[..22])), Err(Error::InvalidPe("unexpected end of file"))));
+        // the path is optional
+        assert_eq!(CodeView::from_pe(&pe(&record[..24])).unwrap().path, "");
+
+        let mut data = pe(&record);
+        put_u32(&mut data, DEBUG_DIRECTORY + 16, record.len() as u32 + 1);
+        assert!(matches!(CodeView::from_pe(&data), Err(Error::InvalidPe("CodeView record out of bounds"))));
+        put_u32(&mut data, DEBUG_DIRECTORY + 24, u32::MAX);
+        assert!(matches!(CodeView::from_pe(&data), Err(Error::InvalidPe("CodeView record out of bounds"))));
+    }
+
+    #[test]
+    fn truncated_file() {
+        let data = pe(&rsds(1, "foo.pdb"));
+        for len in [0, 1, 0x3e, 0x42, 0x60, 0xd0, 0x150, 0x210] {
+            assert!(matches!(CodeView::from_pe(&data[..len]), Err(Error::InvalidPe(_))), "{len:#x}");
+        }
+    }
+
+    #[test]
+    fn no_codeview() {
+        let mut record = rsds(1, "foo.pdb");
+        record[..4].copy_from_slice(b"NB10");
+        assert!(matches!(CodeView::from_pe(&pe(&record)), Err(Error::NoCodeView)));
+
+        let mut data = pe(&rsds(1, "foo.pdb"));
+        put_u32(&mut data, DEBUG_DIRECTORY + 12, 1);
+        assert!(matches!(CodeView::from_pe(&data), Err(Error::NoCodeView)));
+    }
+
+    #[test]
+    fn invalid_headers() {
+        let mut data = pe(&rsds(1, "foo.pdb"));
+        data[0x40] = b'X';
+        assert!(matches!(CodeView::from_pe(&data), Err(Error::InvalidPe("missing PE signature"))));
+        data[0] = b'X';
+        assert!(matches!(CodeView::from_pe(&data), Err(Error::InvalidPe("missing MZ signature"))));
+
+        let mut data = pe(&rsds(1, "foo.pdb"));
+        put_u16(&mut data, 0x44 + 20, 0x107);
+        assert!(matches!(CodeView::from_pe(&data), Err(Error::InvalidPe("unknown optional header magic"))));
+
+        // the debug directory isn't part of any section
+        let mut data = pe(&rsds(1, "foo.pdb"));
+        put_u32(&mut data, 0x44 + 20 + 112 + IMAGE_DIRECTORY_ENTRY_DEBUG * 8, 0x2000);
+        assert!(matches!(CodeView::from_pe(&data), Err(Error::InvalidPe("debug directory outside of all sections"))));
+    }
+}
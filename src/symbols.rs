@@ -0,0 +1,219 @@
+use std::collections::HashMap;
+
+use pdb::{PDB, Source, FallibleIterator, SymbolData, DataSymbol, Error as PdbError};
+
+use crate::dbi::{Sections, read_u16, read_u32};
+use crate::ir::{EnumValue, Phase, Progress};
+use crate::{tpi, Result};
+
+const S_CONSTANT: u16 = 0x1107;
+const S_LPROC32: u16 = 0x110f;
+const S_GPROC32: u16 = 0x1110;
+const S_LPROC32_ID: u16 = 0x1146;
+const S_GPROC32_ID: u16 = 0x1147;
+
+/// Stream number of modules without symbols.
+const NO_STREAM: u16 = 0xffff;
+
+/// The symbols of a pdb which aren't types.
+#[derive(Default)]
+pub(crate) struct Symbols {
+    pub data: Vec<Data>,
+    pub procedures: Vec<Procedure>,
+    pub publics: Vec<Public>,
+    pub udts: Vec<Udt>,
+    pub constants: Vec<Constant>,
+}
+
+/// A global or static variable of the global symbol stream.
+pub(crate) struct Data {
+    /// The qualified name.
+    pub name: String,
+    pub symbol: DataSymbol,
+    pub rva: Option<u32>,
+}
+
+/// A function with debug information of any module.
+pub(crate) struct Procedure {
+    /// The qualified, undecorated name.
+    pub name: String,
+    /// The signature, a `Procedure` or `MemberFunction` type.
+    pub type_index: pdb::TypeIndex,
+    pub rva: Option<u32>,
+    /// Size of the code in bytes.
+    pub len: u32,
+    /// `false` for functions only visible within their translation unit (`S_LPROC32`).
+    pub global: bool,
+}
+
+/// A function of the public symbols.
+pub(crate) struct Public {
+    /// The decorated name.
+    pub name: String,
+    pub rva: Option<u32>,
+}
+
+/// A typedef or the name of a class, union or enum.
+pub(crate) struct Udt {
+    pub name: String,
+    pub type_index: pdb::TypeIndex,
+}
+
+/// A named constant, e.g. a `constexpr` variable.
+pub(crate) struct Constant {
+    /// The qualified name.
+    pub name: String,
+    pub type_index: pdb::TypeIndex,
+    pub value: EnumValue,
+}
+
+/// Reads all data, procedure, public function, user defined type and constant symbols.
+pub(crate) fn read<'s, S: Source<'s> + 's>(pdb: &mut PDB<'s, S>, progress: Option<&dyn Progress>) -> Result<Symbols> {
+    let sections = Sections::read(pdb)?;
+    let mut symbols = read_globals(pdb, &sections)?;
+    symbols.procedures = read_procedures(pdb, &sections, progress)?;
+    Ok(symbols)
+}
+
+/// Reads only the data symbols, which are part of the global symbol stream.
+pub(crate) fn read_data<'s, S: Source<'s> + 's>(pdb: &mut PDB<'s, S>) -> Result<Vec<Data>> {
+    let sections = Sections::read(pdb)?;
+    Ok(read_globals(pdb, &sections)?.data)
+}
+
+fn read_globals<'s, S: Source<'s> + 's>(pdb: &mut PDB<'s, S>, sections: &Sections) -> Result<Symbols> {
+    let table = pdb.global_symbols()?;
+    let mut iter = table.iter();
+    let mut symbols = Symbols::default();
+    while let Some(symbol) = iter.next()? {
+        match symbol.parse() {
+            Ok(SymbolData::DataSymbol(data_symbol)) => symbols.data.push(Data {
+                name: symbol.name()?.to_string().into_owned(),
+                symbol: data_symbol,
+                rva: sections.rva(data_symbol.segment, data_symbol.offset),
+            }),
+            Ok(SymbolData::PublicSymbol(public)) if public.function || public.code => symbols.publics.push(Public {
+                name: symbol.name()?.to_string().into_owned(),
+                rva: sections.rva(public.segment, public.offset),
+            }),
+            // the pdb crate only reads values below 0x8000 and gets the name of the others wrong
+            Ok(SymbolData::Constant(_)) if symbol.raw_kind() == S_CONSTANT => {
+                symbols.constants.extend(constant(symbol.raw_bytes()));
+            }
+            Ok(SymbolData::UserDefinedType(udt)) => symbols.udts.push(Udt {
+                name: symbol.name()?.to_string().into_owned(),
+                type_index: udt.type_index,
+            }),
+            Ok(_) | Err(PdbError::UnimplementedSymbolKind(_)) => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(symbols)
+}
+
+fn constant(data: &[u8]) -> Option<Constant> {
+    // kind, type, value
+    let type_index = read_u32(data, 2).ok()?;
+    let (value, len) = tpi::numeric_leaf(data, 6)?;
+    let name = data.get(6 + len..)?;
+    let name_len = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+    Some(Constant {
+        name: String::from_utf8_lossy(&name[..name_len]).into_owned(),
+        type_index,
+        value,
+    })
+}
+
+/// Reads the `S_GPROC32` and `S_LPROC32` symbols of all modules, which the pdb crate can't
+/// parse.
+fn read_procedures<'s, S: Source<'s> + 's>(pdb: &mut PDB<'s, S>, sections: &Sections, progress: Option<&dyn Progress>) -> Result<Vec<Procedure>> {
+    let function_ids = tpi::read_function_ids(pdb)?;
+    let dbi = pdb.debug_information()?;
+    let modules: Vec<_> = dbi.modules()?.collect()?;
+    let mut procedures = Vec::new();
+    for (i, module) in modules.iter().enumerate() {
+        if let Some(progress) = progress {
+            progress.update(Phase::Symbols, i + 1, modules.len());
+        }
+        if module.info().stream == NO_STREAM {
+            continue;
+        }
+        let info = pdb.module_info(module)?;
+        let mut iter = info.symbols()?;
+        while let Some(symbol) = iter.next()? {
+            let kind = symbol.raw_kind();
+            if !matches!(kind, S_LPROC32 | S_GPROC32 | S_LPROC32_ID | S_GPROC32_ID) {
+                continue;
+            }
+            // kind, parent, end, next, len, debug start, debug end, type, offset, segment, flags
+            let data = symbol.raw_bytes();
+            let type_index = read_u32(data, 26)?;
+            let type_index = match kind {
+                S_LPROC32_ID | S_GPROC32_ID => match function_ids.get(&type_index) {
+                    Some(&type_index) => type_index,
+                    None => continue,
+                },
+                _ => type_index,
+            };
+            let name = data.get(37..).ok_or(PdbError::UnexpectedEof)?;
+            let name_len = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+            procedures.push(Procedure {
+                name: String::from_utf8_lossy(&name[..name_len]).into_owned(),
+                type_index,
+                rva: sections.rva(read_u16(data, 34)?, read_u32(data, 30)?),
+                len: read_u32(data, 14)?,
+                global: matches!(kind, S_GPROC32 | S_GPROC32_ID),
+            });
+        }
+    }
+    Ok(procedures)
+}
+
+/// The RVAs of the variables by their qualified name.
+pub(crate) fn addresses(data: &[Data]) -> HashMap<String, u32> {
+    data.iter()
+        .filter_map(|data| Some((data.name.clone(), data.rva?)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An `S_CONSTANT` record without its length, with a numeric leaf `value`.
+    fn record(type_index: u32, value: &[u8], name: &[u8]) -> Vec<u8> {
+        let mut data = 0x1107u16.to_le_bytes().to_vec();
+        data.extend_from_slice(&type_index.to_le_bytes());
+        data.extend_from_slice(value);
+        data.extend_from_slice(name);
+        data
+    }
+
+    #[test]
+    fn constants() {
+        let parsed = constant(&record(0x74, &42u16.to_le_bytes(), b"answer\0")).unwrap();
+        assert_eq!(parsed.name, "answer");
+        assert_eq!(parsed.type_index, 0x74);
+        assert_eq!(parsed.value, EnumValue::U16(42));
+
+        let mut value = 0x8003u16.to_le_bytes().to_vec();
+        value.extend_from_slice(&(-100_000i32).to_le_bytes());
+        let parsed = constant(&record(0x1234, &value, b"ns::Min\0\0\0")).unwrap();
+        assert_eq!(parsed.name, "ns::Min");
+        assert_eq!(parsed.type_index, 0x1234);
+        assert_eq!(parsed.value, EnumValue::I32(-100_000));
+
+        // the name may fill the record without a terminator
+        let parsed = constant(&record(0x74, &1u16.to_le_bytes(), b"one")).unwrap();
+        assert_eq!(parsed.name, "one");
+    }
+
+    #[test]
+    fn invalid_constants() {
+        assert!(constant(&[0x07, 0x11, 0x74, 0]).is_none());
+        // truncated value
+        assert!(constant(&record(0x74, &[0x04, 0x80, 1, 2], b"")).is_none());
+        // LF_REAL32
+        assert!(constant(&record(0x40, &[0x05, 0x80, 0, 0, 0x80, 0x3f], b"f\0")).is_none());
+    }
+}
@@ -0,0 +1,284 @@
+use std::collections::HashMap;
+
+use pdb::{PDB, Source};
+
+use crate::dbi::{read_u16, read_u32, read_u64};
+use crate::ir::{EnumValue, VtableShape, VtableSlotKind};
+use crate::{Error, Result};
+
+/// Stream number of the type information stream.
+const TPI_STREAM: u32 = 2;
+/// Stream number of the id information stream, which has the same format.
+const IPI_STREAM: u32 = 4;
+
+const LF_VTSHAPE: u16 = 0x000a;
+const LF_DERIVED: u16 = 0x1204;
+const LF_CLASS: u16 = 0x1504;
+const LF_STRUCTURE: u16 = 0x1505;
+const LF_UNION: u16 = 0x1506;
+const LF_ENUM: u16 = 0x1507;
+const LF_INTERFACE: u16 = 0x1519;
+const LF_FUNC_ID: u16 = 0x1601;
+const LF_MFUNC_ID: u16 = 0x1602;
+
+/// `CV_prop_t::hasuniquename`
+const HAS_UNIQUE_NAME: u16 = 0x200;
+
+/// The parts of the type records which the pdb crate doesn't parse.
+#[derive(Debug, Default)]
+pub(crate) struct RawTypes {
+    /// Decorated unique names of all classes, unions and enums which have one.
+    ///
+    /// Unlike the plain names, unique names also distinguish types with the same name from
+    /// different anonymous namespaces or functions.
+    pub unique_names: HashMap<pdb::TypeIndex, String>,
+    /// All `LF_VTSHAPE` records.
+    pub vtable_shapes: HashMap<pdb::TypeIndex, VtableShape>,
+    /// All `LF_DERIVED` records, the lists of classes derived from a class.
+    pub derived_lists: HashMap<pdb::TypeIndex, Vec<pdb::TypeIndex>>,
+}
+
+pub(crate) fn read<'s, S: Source<'s> + 's>(pdb: &mut PDB<'s, S>) -> Result<RawTypes> {
+    let mut raw = RawTypes::default();
+    read_records(pdb, TPI_STREAM, |index, kind, record| {
+        if kind == LF_VTSHAPE {
+            raw.vtable_shapes.insert(index, vtable_shape(record)?);
+        } else if kind == LF_DERIVED {
+            raw.derived_lists.insert(index, derived_list(record)?);
+        } else if let Some(name) = unique_name(kind, record) {
+            raw.unique_names.insert(index, name);
+        }
+        Ok(())
+    })?;
+    Ok(raw)
+}
+
+/// Reads the function ids of the IPI stream, which `S_GPROC32_ID` symbols refer to, and
+/// returns the type index of the signature of each.
+///
+/// Older pdbs without IPI stream don't have any ids.
+pub(crate) fn read_function_ids<'s, S: Source<'s> + 's>(pdb: &mut PDB<'s, S>) -> Result<HashMap<u32, pdb::TypeIndex>> {
+    let mut ids = HashMap::new();
+    let res = read_records(pdb, IPI_STREAM, |index, kind, record| {
+        // scope or parent type, type
+        if let LF_FUNC_ID | LF_MFUNC_ID = kind {
+            ids.insert(index, read_u32(record, 4)?);
+        }
+        Ok(())
+    });
+    match res {
+        Ok(()) | Err(Error::Pdb(pdb::Error::StreamNotFound(_))) => Ok(ids),
+        Err(e) => Err(e),
+    }
+}
+
+/// Calls `f` with the index, kind and data of each record of a type stream.
+fn read_records<'s, S, F>(pdb: &mut PDB<'s, S>, stream: u32, mut f: F) -> Result<()>
+where
+    S: Source<'s> + 's,
+    F: FnMut(u32, u16, &[u8]) -> Result<()>,
+{
+    let stream = pdb.raw_stream(stream)?;
+    let mut buf = stream.parse_buffer();
+    let len = buf.len();
+    let data = buf.take(len)?;
+    // https://llvm.org/docs/PDB/TpiStream.html#tpi-header
+    let header_size = read_u32(data, 4)? as usize;
+    let mut index = read_u32(data, 8)?;
+
+    let mut offset = header_size;
+    while offset + 4 <= data.len() {
+        let record_len = read_u16(data, offset)? as usize;
+        let kind = read_u16(data, offset + 2)?;
+        let record = data.get(offset + 4..offset + 2 + record_len)
+            .ok_or(pdb::Error::UnexpectedEof)?;
+        f(index, kind, record)?;
+        offset += 2 + record_len;
+        index += 1;
+    }
+    Ok(())
+}
+
+fn vtable_shape(record: &[u8]) -> Result<VtableShape> {
+    let count = read_u16(record, 0)? as usize;
+    // two 4 bit `CV_VTS_desc_e` per byte, the first one in the low nibble
+    let descriptors = record.get(2..2 + count.div_ceil(2)).ok_or(pdb::Error::UnexpectedEof)?;
+    let slots = (0..count)
+        .map(|i| VtableSlotKind::from((descriptors[i / 2] >> (i % 2 * 4)) & 0xf))
+        .collect();
+    Ok(VtableShape { slots })
+}
+
+fn derived_list(record: &[u8]) -> Result<Vec<pdb::TypeIndex>> {
+    let count = read_u32(record, 0)? as usize;
+    (0..count).map(|i| read_u32(record, 4 + i * 4)).collect()
+}
+
+fn unique_name(kind: u16, record: &[u8]) -> Option<String> {
+    let properties = read_u16(record, 2).ok()?;
+    if properties & HAS_UNIQUE_NAME == 0 {
+        return None;
+    }
+    // skip everything up to the name
+    let name_offset = match kind {
+        // count, properties, fields, derived_from, vtable_shape, size
+        LF_CLASS | LF_STRUCTURE | LF_INTERFACE => 16 + numeric_leaf_len(record, 16)?,
+        // count, properties, fields, size
+        LF_UNION => 8 + numeric_leaf_len(record, 8)?,
+        // count, properties, underlying_type, fields
+        LF_ENUM => 12,
+        _ => return None,
+    };
+    let rest = record.get(name_offset..)?;
+    let name_len = rest.iter().position(|&b| b == 0)?;
+    let rest = &rest[name_len + 1..];
+    let unique_len = rest.iter().position(|&b| b == 0)?;
+    Some(String::from_utf8_lossy(&rest[..unique_len]).into_owned())
+}
+
+/// Length of the variable-length numeric leaf at `offset`.
+fn numeric_leaf_len(record: &[u8], offset: usize) -> Option<usize> {
+    numeric_leaf(record, offset).map(|(_, len)| len)
+}
+
+/// Reads the variable-length numeric leaf at `offset` and returns its value and length.
+pub(crate) fn numeric_leaf(record: &[u8], offset: usize) -> Option<(EnumValue, usize)> {
+    let kind = read_u16(record, offset).ok()?;
+    let value = record.get(offset + 2..)?;
+    Some(match kind {
+        value if value < 0x8000 => (EnumValue::U16(value), 2),
+        // LF_CHAR
+        0x8000 => (EnumValue::I8(*value.first()? as i8), 3),
+        // LF_SHORT
+        0x8001 => (EnumValue::I16(read_u16(value, 0).ok()? as i16), 4),
+        // LF_USHORT
+        0x8002 => (EnumValue::U16(read_u16(value, 0).ok()?), 4),
+        // LF_LONG
+        0x8003 => (EnumValue::I32(read_u32(value, 0).ok()? as i32), 6),
+        // LF_ULONG
+        0x8004 => (EnumValue::U32(read_u32(value, 0).ok()?), 6),
+        // LF_QUADWORD
+        0x8009 => (EnumValue::I64(read_u64(value, 0).ok()? as i64), 10),
+        // LF_UQUADWORD
+        0x800a => (EnumValue::U64(read_u64(value, 0).ok()?), 10),
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A numeric leaf of kind `kind` followed by `value`.
+    fn leaf(kind: u16, value: &[u8]) -> Vec<u8> {
+        let mut data = kind.to_le_bytes().to_vec();
+        data.extend_from_slice(value);
+        data
+    }
+
+    #[test]
+    fn numeric_leaves() {
+        assert_eq!(numeric_leaf(&leaf(0x1234, &[]), 0), Some((EnumValue::U16(0x1234), 2)));
+        assert_eq!(numeric_leaf(&leaf(0x8000, &[0xfe]), 0), Some((EnumValue::I8(-2), 3)));
+        assert_eq!(numeric_leaf(&leaf(0x8001, &(-300i16).to_le_bytes()), 0), Some((EnumValue::I16(-300), 4)));
+        assert_eq!(numeric_leaf(&leaf(0x8002, &0xfffeu16.to_le_bytes()), 0), Some((EnumValue::U16(0xfffe), 4)));
+        assert_eq!(numeric_leaf(&leaf(0x8003, &(-70000i32).to_le_bytes()), 0), Some((EnumValue::I32(-70000), 6)));
+        assert_eq!(numeric_leaf(&leaf(0x8004, &0xffff_fffeu32.to_le_bytes()), 0), Some((EnumValue::U32(0xffff_fffe), 6)));
+        assert_eq!(numeric_leaf(&leaf(0x8009, &i64::MIN.to_le_bytes()), 0), Some((EnumValue::I64(i64::MIN), 10)));
+        assert_eq!(numeric_leaf(&leaf(0x800a, &u64::MAX.to_le_bytes()), 0), Some((EnumValue::U64(u64::MAX), 10)));
+        // at an offset into the record
+        let mut record = vec![0xaa; 3];
+        record.extend(leaf(0x8001, &7i16.to_le_bytes()));
+        assert_eq!(numeric_leaf(&record, 3), Some((EnumValue::I16(7), 4)));
+    }
+
+    #[test]
+    fn invalid_numeric_leaves() {
+        // truncated values
+        assert_eq!(numeric_leaf(&leaf(0x8000, &[]), 0), None);
+        assert_eq!(numeric_leaf(&leaf(0x8003, &[1, 2, 3]), 0), None);
+        assert_eq!(numeric_leaf(&leaf(0x800a, &[0; 7]), 0), None);
+        assert_eq!(numeric_leaf(&[0x01], 0), None);
+        assert_eq!(numeric_leaf(&leaf(0x1234, &[]), 2), None);
+        // LF_REAL32 and other unsupported kinds
+        assert_eq!(numeric_leaf(&leaf(0x8005, &[0; 4]), 0), None);
+    }
+
+    /// The fixed part of an `LF_CLASS` record up to the size, followed by `size` and the names.
+    fn class(properties: u16, size: &[u8], names: &[&str]) -> Vec<u8> {
+        let mut record = vec![0; 16];
+        record[2..4].copy_from_slice(&properties.to_le_bytes());
+        record.extend_from_slice(size);
+        for name in names {
+            record.extend_from_slice(name.as_bytes());
+            record.push(0);
+        }
+        record
+    }
+
+    #[test]
+    fn unique_names() {
+        let record = class(HAS_UNIQUE_NAME, &8u16.to_le_bytes(), &["Foo", ".?AVFoo@@"]);
+        assert_eq!(unique_name(LF_CLASS, &record).as_deref(), Some(".?AVFoo@@"));
+        assert_eq!(unique_name(LF_STRUCTURE, &record).as_deref(), Some(".?AVFoo@@"));
+        assert_eq!(unique_name(LF_INTERFACE, &record).as_deref(), Some(".?AVFoo@@"));
+        // the size is a numeric leaf of its own
+        let record = class(HAS_UNIQUE_NAME, &leaf(0x8004, &0x10000u32.to_le_bytes()), &["Big", ".?AUBig@@"]);
+        assert_eq!(unique_name(LF_STRUCTURE, &record).as_deref(), Some(".?AUBig@@"));
+
+        let mut union = vec![0; 8];
+        union[2..4].copy_from_slice(&HAS_UNIQUE_NAME.to_le_bytes());
+        union.extend_from_slice(&4u16.to_le_bytes());
+        union.extend_from_slice(b"U\0.?ATU@@\0");
+        assert_eq!(unique_name(LF_UNION, &union).as_deref(), Some(".?ATU@@"));
+
+        let mut enumeration = vec![0; 12];
+        enumeration[2..4].copy_from_slice(&HAS_UNIQUE_NAME.to_le_bytes());
+        enumeration.extend_from_slice(b"E\0.?AW4E@@\0");
+        assert_eq!(unique_name(LF_ENUM, &enumeration).as_deref(), Some(".?AW4E@@"));
+    }
+
+    #[test]
+    fn missing_unique_names() {
+        // flag not set
+        let record = class(0, &8u16.to_le_bytes(), &["Foo", ".?AVFoo@@"]);
+        assert_eq!(unique_name(LF_CLASS, &record), None);
+        // not terminated
+        let mut record = class(HAS_UNIQUE_NAME, &8u16.to_le_bytes(), &["Foo"]);
+        record.extend_from_slice(b".?AVFoo@@");
+        assert_eq!(unique_name(LF_CLASS, &record), None);
+        // unsupported size leaf
+        let record = class(HAS_UNIQUE_NAME, &leaf(0x8005, &[0; 4]), &["Foo", ".?AVFoo@@"]);
+        assert_eq!(unique_name(LF_CLASS, &record), None);
+        // not a user defined type
+        let record = class(HAS_UNIQUE_NAME, &8u16.to_le_bytes(), &["Foo", ".?AVFoo@@"]);
+        assert_eq!(unique_name(LF_VTSHAPE, &record), None);
+        assert_eq!(unique_name(LF_CLASS, &[]), None);
+    }
+
+    #[test]
+    fn vtable_shapes() {
+        // Near, Thin, Near32, Unknown(15), Far32
+        let shape = vtable_shape(&[5, 0, 0x20, 0xf5, 0x06]).unwrap();
+        assert_eq!(shape.slots, [
+            VtableSlotKind::Near,
+            VtableSlotKind::Thin,
+            VtableSlotKind::Near32,
+            VtableSlotKind::Unknown(15),
+            VtableSlotKind::Far32,
+        ]);
+        assert!(vtable_shape(&[0, 0]).unwrap().slots.is_empty());
+        // three slots need two bytes of descriptors
+        assert!(vtable_shape(&[3, 0, 0x00]).is_err());
+        assert!(vtable_shape(&[1]).is_err());
+    }
+
+    #[test]
+    fn derived_lists() {
+        let mut record = 2u32.to_le_bytes().to_vec();
+        record.extend_from_slice(&0x1001u32.to_le_bytes());
+        record.extend_from_slice(&0x1002u32.to_le_bytes());
+        assert_eq!(derived_list(&record).unwrap(), [0x1001, 0x1002]);
+        assert!(derived_list(&record[..8]).is_err());
+    }
+}